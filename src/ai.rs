@@ -1,28 +1,115 @@
 pub mod ai {
-    use std::fs;
-    use std::io::Write;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
 
-    use crate::game::oxydized2048::{Action, Game, GameState};
+    use crate::game::oxydized2048::{Action, Board, Game, GameConfig};
+    use crate::exploration::exploration::{Exploration, Explorer};
+    use crate::heuristic::heuristic::Evaluation;
+    use crate::metrics::metrics::Episode;
+    use crate::model::model::Hyperparameters;
+    use crate::player::player::{play_from, Player, Report, Turn};
+    use crate::termination::termination::{Budget, Limits};
     use rurel::mdp::{Agent, State};
-    use rurel::strategy::explore::{RandomExploration};
+    use rurel::strategy::explore::{ExplorationStrategy, RandomExploration};
     use rurel::strategy::learn::QLearning;
     use rurel::strategy::terminate::{FixedIterations, TerminationStrategy};
     use rurel::AgentTrainer;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{HashMap, HashSet};
+    use std::process;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
     use std::time::{Instant};
 
-    impl State for Game {
+    /// Where the menu and command line keep the Q-table unless told otherwise.
+    pub const LEARNED_STATE: &str = "learned_state.ron";
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    // The position the last move was played from, as far as the evaluation can tell.
+    fn before_last_move(game: &Game) -> Game {
+        let mut before = game.clone();
+        before.board = game.prev_board.clone();
+        before.score = game.prev_score;
+        before.merged_last = 0;
+        before
+    }
+
+    /// What the Q-table learns values for: the board alone. Score, history and
+    /// the last move don't change what can happen next, so every way of
+    /// reaching a board shares its values.
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct LearningState {
+        pub board: Board,
+        // Worked out from the game that reached the board. Not part of the
+        // state's identity, and not stored with the Q-table.
+        #[serde(skip)]
+        reward: f64,
+        #[serde(skip)]
+        gameover: bool,
+        #[serde(skip)]
+        actions: Vec<Action>,
+    }
+
+    impl LearningState {
+        /// The state `game` reached. Rewards are the change in `evaluate`
+        /// across the last move, so the evaluation never leaks into the score
+        /// the player sees.
+        pub fn new(game: &Game, evaluate: Evaluation) -> LearningState {
+            let value = evaluate(game);
+            let gameover = game.is_gameover();
+            LearningState {
+                board: game.board.clone(),
+                reward: if gameover { -value } else { value - evaluate(&before_last_move(game)) },
+                gameover,
+                actions: game.get_valid_actions(),
+            }
+        }
+
+        /// Whether the game ended on reaching this state. The agent's next
+        /// action starts a new game instead of playing a move.
+        pub fn is_terminal(&self) -> bool {
+            self.gameover
+        }
+
+        /// A bare state for looking up or storing values, with no reward or moves.
+        pub fn from_board(board: Board) -> LearningState {
+            LearningState {
+                board,
+                reward: 0.0,
+                gameover: false,
+                actions: Vec::new(),
+            }
+        }
+    }
+
+    impl PartialEq for LearningState {
+        fn eq(&self, other: &Self) -> bool {
+            self.board == other.board
+        }
+    }
+
+    impl Eq for LearningState {}
+
+    impl Hash for LearningState {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.board.hash(state);
+        }
+    }
+
+    impl State for LearningState {
         type A = Action;
         fn reward(&self) -> f64 {
-            let score = self.calc_score();
-            if self.is_gameover() {
-                println!("Gameover");
-                -(score as f64)
-            } else {
-                Into::<f64>::into(score) - Into::<f64>::into(self.prev_score)
-            }
+            self.reward
         }
         fn actions(&self) -> Vec<Action> {
-            let actions = self.get_valid_actions();
+            let actions = self.actions.clone();
+            if actions.is_empty() {
+                // A finished game has no moves, but rurel still needs one to pick;
+                // MyAgent treats any action on a finished game as starting a new one.
+                return vec![Action::MergeDown];
+            }
             if actions.contains(&Action::MergeDown) && actions.contains(&Action::MergeRight) {
                 return vec![Action::MergeDown, Action::MergeRight];
             } else if actions.contains(&Action::MergeDown) {
@@ -35,163 +122,498 @@ pub mod ai {
         }
     }  
 
+    // Training never undoes a move, so skip recording history on every action.
+    fn training_game(config: &GameConfig, seed: Option<u64>) -> Game {
+        let mut game = match seed {
+            Some(seed) => Game::with_config_and_seed(config.clone(), seed),
+            None => Game::with_config(config.clone()),
+        };
+        game.set_history_depth(0);
+        game
+    }
+
+    // What training carries from run to run.
+    #[derive(Clone)]
+    struct Progress {
+        runs: u32,
+        steps: u64,
+        // Picks the training moves
+        explorer: Explorer,
+        // Every finished game counts against it, shared by all workers
+        budget: Arc<Mutex<Budget>>,
+        // The states the Q-table holds values for, hashed; cheaper than
+        // asking the trainer for its size
+        known: HashSet<u64>,
+        start: Instant,
+        // Games finished since last asked
+        episodes: Vec<Episode>,
+    }
+
+    impl Progress {
+        fn new(trainer: &AgentTrainer<LearningState>, exploration: &Exploration, limits: &Limits) -> Progress {
+            Progress {
+                known: trainer.export_learned_values().keys().map(state_key).collect(),
+                ..Progress::untracked(exploration, limits)
+            }
+        }
+
+        // For training that reports nothing
+        fn untracked(exploration: &Exploration, limits: &Limits) -> Progress {
+            Progress {
+                runs: 0,
+                steps: 0,
+                explorer: Explorer::new(exploration.clone()),
+                budget: Arc::new(Mutex::new(Budget::new(limits.clone()))),
+                known: HashSet::new(),
+                start: Instant::now(),
+                episodes: Vec::new(),
+            }
+        }
+
+        fn out_of_budget(&self) -> bool {
+            self.budget.lock().unwrap().stopped().is_some()
+        }
+    }
+
+    fn state_key(state: &LearningState) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hasher.finish()
+    }
+
     struct MyAgent {
-        state: Game,
+        game: Game,
+        state: LearningState,
+        progress: Progress,
+        evaluation: Evaluation,
+        // Steps and summed reward of the game in play
+        steps: u32,
+        reward: f64,
+    }
+    impl MyAgent {
+        fn new(game: Game, progress: Progress, evaluation: Evaluation) -> MyAgent {
+            let state = LearningState::new(&game, evaluation);
+            MyAgent { game, state, progress, evaluation, steps: 0, reward: 0.0 }
+        }
     }
-    impl Agent<Game> for MyAgent {
-        fn current_state(&self) -> &Game {
+    impl Agent<LearningState> for MyAgent {
+        fn current_state(&self) -> &LearningState {
             &self.state
         }
         fn take_action(&mut self, action: &Action) -> () {
-            self.state.action(action);
+            // The trainer stores a value for the state acted from
+            self.progress.known.insert(state_key(&self.state));
+            if self.game.is_gameover() {
+                self.game.reset();
+                self.state = LearningState::new(&self.game, self.evaluation);
+                self.steps = 0;
+                self.reward = 0.0;
+                return;
+            }
+
+            self.game.action(action);
+            self.state = LearningState::new(&self.game, self.evaluation);
+            self.steps += 1;
+            self.reward += self.state.reward;
+            if self.state.is_terminal() {
+                let stats = self.game.stats();
+                self.progress.budget.lock().unwrap().end_episode(stats.score);
+                self.progress.episodes.push(Episode {
+                    steps: self.steps,
+                    score: stats.score,
+                    max_tile: stats.max_tile,
+                    reward: self.reward,
+                    table_size: self.progress.known.len(),
+                    seconds: self.progress.start.elapsed().as_secs_f64(),
+                });
+            }
         }
     }
 
 
-    pub fn train(
-        trainer: &mut AgentTrainer<Game>,
-        alpha: f64,
-        gamma: f64,
-        initial_value: f64,
-        num_iter: u32,
-        num_runs: u32,
-    ) -> &mut AgentTrainer<Game> {
-        let learning_strat = &QLearning::new(alpha, gamma, initial_value);
-        let exploration_strat = &RandomExploration::new();
-
-        let start = Instant::now();
-        let mut sum = 0;
-        for i in 0..num_runs {
-            let now = Instant::now();
-
-            println!("Run: {}/{}", i, num_runs);
-            let mut agent = MyAgent { state: Game::new() };
-            trainer.train(
-                &mut agent,
-                learning_strat,
-                &mut FixedIterations::new(num_iter),
-                exploration_strat,
-            );
+    /// Makes Ctrl-C ask `train` and `test_and_train` to stop at the next safe
+    /// point so their progress can be saved. A second Ctrl-C quits at once.
+    pub fn stop_on_interrupt() {
+        // Only one handler per process; a second call keeps the first
+        let _ = ctrlc::set_handler(|| {
+            if STOP.swap(true, Ordering::SeqCst) {
+                process::exit(130);
+            }
+            println!("Stopping after this run, Ctrl-C again to quit without saving...");
+        });
+    }
+
+    /// Asks training to stop as a first Ctrl-C does, or takes that back.
+    pub fn request_stop(stop: bool) {
+        STOP.store(stop, Ordering::SeqCst);
+    }
+
+    fn stop_requested() -> bool {
+        STOP.load(Ordering::SeqCst)
+    }
+
+    type Values = HashMap<LearningState, HashMap<Action, f64>>;
+
+    /// How long `train` runs and how it spreads the work.
+    pub struct Schedule {
+        /// Training steps per run, after which the run finishes the game in play.
+        pub iterations: u32,
+        /// None to train until `limits` stop it.
+        pub runs: Option<u32>,
+        /// Limits judged as each training game ends. A run stopped by one
+        /// still finishes its game, as do other workers' games in play.
+        pub limits: Limits,
+        /// Workers training at once. Each trains its own copy of the values
+        /// and the copies are averaged at every checkpoint.
+        pub threads: usize,
+        /// Runs between checkpoints, 0 for none. Several workers only hand
+        /// over their games at a checkpoint, or at the end when there is none.
+        pub checkpoint_every: u32,
+        /// Deals each run's games from this plus the run number, when set.
+        pub seed: Option<u64>,
+    }
+
+    /// Trains for up to `schedule.runs` runs, handing every finished game to
+    /// `on_episode` and the trainer and the runs and steps done so far to
+    /// `checkpoint` every `schedule.checkpoint_every` runs. With several
+    /// threads, games are handed over as each round ends, so with no
+    /// checkpoints they all come at the end. Returns the runs
+    /// and steps played, fewer runs than asked if stopped by Ctrl-C or
+    /// `schedule.limits`; a run cut short by the limits counts.
+    pub fn train<E, F>(
+        trainer: &mut AgentTrainer<LearningState>,
+        hyperparameters: &Hyperparameters,
+        schedule: &Schedule,
+        config: &GameConfig,
+        mut on_episode: E,
+        mut checkpoint: F,
+    ) -> (u32, u64)
+    where
+        E: FnMut(Episode),
+        F: FnMut(&AgentTrainer<LearningState>, u32, u64),
+    {
+        // Workers only share what they learned when a round ends, so rounds
+        // run from one checkpoint to the next; one thread trains run by run
+        let total = schedule.runs.unwrap_or(u32::MAX);
+        let round = match (schedule.threads, schedule.checkpoint_every) {
+            (0 | 1, _) => 1,
+            (_, 0) => total,
+            (_, every) => every,
+        };
 
-            sum += (Instant::now() - now).as_millis();
-            let average_runtime = (sum as f64) / ((i+1) as f64);
-            let estimated_remaining = (average_runtime/1000.0) * ((num_runs - (i+1)) as f64);
-            let total = (Instant::now() - start).as_secs();
-            print!("Elapsed: {}:{} | ", total/60, total%60);
-            println!("Remaining: {}:{}", (estimated_remaining/60.0) as i64, (estimated_remaining%60.0) as i64);
+        let mut progress = Progress::new(trainer, &hyperparameters.exploration, &schedule.limits);
+        while progress.runs < total && !stop_requested() && !progress.out_of_budget() {
+            match schedule.runs {
+                Some(runs) => println!("Run: {}/{}", progress.runs, runs),
+                None => println!("Run: {}", progress.runs),
+            }
+            let runs = round.min(total - progress.runs);
+            if runs == 1 {
+                let run = progress.runs;
+                progress = train_run(trainer, hyperparameters, schedule, config, progress, run);
+                progress.runs += 1;
+            } else {
+                progress = train_round(trainer, hyperparameters, schedule, runs, config, progress);
+            }
+            let done = progress.runs;
+            for episode in progress.episodes.drain(..) {
+                on_episode(episode);
+            }
+
+            let elapsed = progress.start.elapsed().as_secs_f64();
+            print!("Elapsed: {}:{}", (elapsed/60.0) as i64, (elapsed%60.0) as i64);
+            match schedule.runs {
+                Some(runs) => {
+                    let remaining = elapsed / done.max(1) as f64 * (runs - done) as f64;
+                    println!(" | Remaining: {}:{}", (remaining/60.0) as i64, (remaining%60.0) as i64);
+                }
+                None => println!(),
+            }
+
+            let finished = done == total || progress.out_of_budget();
+            if schedule.checkpoint_every > 0 && done.is_multiple_of(schedule.checkpoint_every) && !finished {
+                checkpoint(trainer, done, progress.steps);
+            }
         }
-        trainer
+        let budget = progress.budget.lock().unwrap();
+        if let Some(reason) = budget.stopped() {
+            println!("Stopped after {} training games: {}", budget.episodes(), reason);
+        }
+        (progress.runs, progress.steps)
     }
 
-    pub fn test_and_train(
-        trainer: &mut AgentTrainer<Game>,
-        alpha: f64,
-        gamma: f64,
-        initial_value: f64,
-        num_iter: u32,
-        num_games: u32,
-    ) -> (&mut AgentTrainer<Game>, u32) {
-        let mut test_game = Game::new();
-        let mut high_score = 0;
-        let learning_strat = &QLearning::new(alpha, gamma, initial_value);
-        let exploration_strat = &RandomExploration::new();
+    // Plays training run number `run` on a new game, to the end of the game
+    // in play once the schedule's steps are up. Decays and deals follow the
+    // run number, so they move on alike however many threads train.
+    fn train_run(
+        trainer: &mut AgentTrainer<LearningState>,
+        hyperparameters: &Hyperparameters,
+        schedule: &Schedule,
+        config: &GameConfig,
+        progress: Progress,
+        run: u32,
+    ) -> Progress {
+        let budget = progress.budget.clone();
+        let game = training_game(config, schedule.seed.map(|seed| seed.wrapping_add(run as u64)));
+        let mut agent = MyAgent::new(game, progress, hyperparameters.evaluation());
+        agent.progress.explorer.set_step(run as u64 * schedule.iterations as u64);
+        let iterations = FixedIterations::new(schedule.iterations);
+        let mut termination = Counted::new(Budgeted::new(ToGameEnd::new(iterations), budget));
+        train_with(trainer, hyperparameters, &mut agent, &mut termination);
+        agent.progress.steps += termination.steps;
+        agent.progress
+    }
 
-        let mut games_played = 0;
-        let mut steps = 0;
+    // Trains until `termination` says to stop, with moves picked by the
+    // agent's explorer. Random exploration leaves the whole loop to rurel;
+    // anything else needs the learned values in view, so rurel is handed one
+    // step at a time with the move already picked.
+    fn train_with<T: TerminationStrategy<LearningState>>(
+        trainer: &mut AgentTrainer<LearningState>,
+        hyperparameters: &Hyperparameters,
+        agent: &mut MyAgent,
+        termination: &mut T,
+    ) {
+        let learning_strat = &QLearning::new(
+            hyperparameters.alpha,
+            hyperparameters.gamma,
+            hyperparameters.initial_value,
+        );
+        if agent.progress.explorer.exploration() == &Exploration::Random {
+            trainer.train(agent, learning_strat, termination, &RandomExploration::new());
+            return;
+        }
         loop {
-            steps += 1;
-            if let Some(action) = trainer.best_action(&test_game) {
-                //println!("Got action from trainer");
-                let action_result = test_game.action(&action);
-                match action_result {
-                    GameState::Gameover => {
-                        println!("Gameover");
-                        games_played += 1;
-
-                        let mut agent = MyAgent { state: Game::new() };
-                        trainer.train(
-                            &mut agent,
-                            learning_strat,
-                            &mut FixedIterations::new(num_iter),
-                            exploration_strat,
-                        );
-
-                        if games_played > num_games {
-                            return (trainer, high_score);
-                        }
-                    }
-                    GameState::InvalidMove => test_game.reset(),
-                    GameState::Ok => {
-                        let score = test_game.calc_score();
-                        if score >= high_score {
-                            high_score = score;
-                            println!("New high score: {}", score);
-                            test_game.display();
-                        } else if steps % 10 == 0 {
-                            println!("Score: {}", score);
-                            test_game.display();
+            let state = &agent.state;
+            let action = agent.progress.explorer.choose(
+                state_key(state),
+                &state.actions(),
+                trainer.expected_values(state),
+                hyperparameters.initial_value,
+            );
+            trainer.train(agent, learning_strat, &mut OneStep, &Take(action));
+            if termination.should_stop(&agent.state) {
+                return;
+            }
+        }
+    }
+
+    // Plays a move picked beforehand.
+    struct Take(Action);
+
+    impl ExplorationStrategy<LearningState> for Take {
+        fn pick_action(&self, agent: &mut dyn Agent<LearningState>) -> Action {
+            agent.take_action(&self.0);
+            self.0.clone()
+        }
+    }
+
+    struct OneStep;
+
+    impl TerminationStrategy<LearningState> for OneStep {
+        fn should_stop(&mut self, _: &LearningState) -> bool {
+            true
+        }
+    }
+
+    // Shares `runs` runs among the schedule's workers, each starting from a
+    // copy of the trainer's values, then averages what they learned back into
+    // it. Returns the workers' progress combined.
+    fn train_round(
+        trainer: &mut AgentTrainer<LearningState>,
+        hyperparameters: &Hyperparameters,
+        schedule: &Schedule,
+        runs: u32,
+        config: &GameConfig,
+        mut progress: Progress,
+    ) -> Progress {
+        let base = trainer.export_learned_values();
+        let next = AtomicU32::new(0);
+        let workers: Vec<(Values, u32, Progress)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..schedule.threads.min(runs as usize))
+                .map(|_| {
+                    let mut progress = progress.clone();
+                    let (base, next) = (&base, &next);
+                    scope.spawn(move || {
+                        let mut worker = AgentTrainer::new();
+                        worker.import_state(base.clone());
+                        let mut done = 0;
+                        while !stop_requested() && !progress.out_of_budget() {
+                            let index = next.fetch_add(1, Ordering::SeqCst);
+                            if index >= runs {
+                                break;
+                            }
+                            let run = progress.runs + index;
+                            progress = train_run(&mut worker, hyperparameters, schedule, config, progress, run);
+                            done += 1;
                         }
+                        (worker.export_learned_values(), done, progress)
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+
+        let explorer = progress.explorer.clone();
+        let steps = progress.steps;
+        let mut tables = Vec::new();
+        for (values, done, worker) in workers {
+            progress.runs += done;
+            progress.steps += worker.steps - steps;
+            tables.push(values);
+            progress.explorer.absorb(&explorer, &worker.explorer);
+            progress.known.extend(worker.known);
+            progress.episodes.extend(worker.episodes);
+        }
+        progress.episodes.sort_by(|a, b| a.seconds.total_cmp(&b.seconds));
+        trainer.import_state(average_changes(base, tables));
+        progress
+    }
+
+    /// Sets each value any of `tables` changed from `base` to the mean of the
+    /// changed values, keeping the rest of `base` as it was.
+    fn average_changes<I: IntoIterator<Item = Values>>(mut base: Values, tables: I) -> Values {
+        let mut changes: HashMap<LearningState, HashMap<Action, (f64, u32)>> = HashMap::new();
+        for table in tables {
+            for (state, values) in table {
+                let old = base.get(&state);
+                for (action, value) in values {
+                    if old.and_then(|old| old.get(&action)) == Some(&value) {
+                        continue;
                     }
+                    let change = changes.entry(state.clone()).or_default().entry(action).or_insert((0.0, 0));
+                    change.0 += value;
+                    change.1 += 1;
                 }
-            } else {
-                let mut agent = MyAgent {
-                    state: test_game.clone(),
-                };
-                trainer.train(
-                    &mut agent,
-                    learning_strat,
-                    &mut NumGames::new(num_iter),
-                    exploration_strat,
-                );
             }
         }
+        for (state, values) in changes {
+            let entry = base.entry(state).or_default();
+            for (action, (sum, count)) in values {
+                entry.insert(action, sum / count as f64);
+            }
+        }
+        base
     }
 
-    pub fn test(trainer: &AgentTrainer<Game>) -> u32 {
+    /// Plays test games with the learned values, retraining for `num_iter`
+    /// steps and the rest of that game after each of `num_games` losses, and
+    /// for `num_iter` games whenever the test game reaches a board with no
+    /// values. Stops early once the training games use up `limits`. With a
+    /// `seed`, the test games are dealt from it and each retraining from it
+    /// plus the losses so far.
+    pub fn test_and_train<'a>(
+        trainer: &'a mut AgentTrainer<LearningState>,
+        hyperparameters: &Hyperparameters,
+        num_iter: u32,
+        num_games: u32,
+        limits: &Limits,
+        config: &GameConfig,
+        seed: Option<u64>,
+    ) -> (&'a mut AgentTrainer<LearningState>, u32, u64) {
+        let progress = Progress::untracked(&hyperparameters.exploration, limits);
+        let mut tester = Tester {
+            trainer,
+            hyperparameters,
+            num_iter,
+            budget: progress.budget.clone(),
+            progress: Some(progress),
+            trained: 0,
+        };
+
+        // Nor is a test game's move undone
+        let mut test_game = training_game(config, seed);
         let mut high_score = 0;
-        let mut test_game = Game::new();
-        let mut steps = 0;
+        let mut games_played = 0;
         loop {
-            steps += 1;
-            if let Some(action) = trainer.best_action(&test_game) {
-                let action_result = test_game.action(&action);
-                match action_result {
-                    GameState::Gameover => {
-                        println!("Gameover");
-                        return high_score;
-                    }
-                    GameState::InvalidMove => {
-                        println!("Invalid move");
-                        return high_score;
-                    }
-                    GameState::Ok => {
-                        let score = test_game.calc_score();
-                        if score >= high_score {
-                            high_score = score;
-                            println!("New high score: {}", score);
-                            test_game.display();
-                        } else if steps % 10 == 0 {
-                            println!("Score: {}", score);
-                            test_game.display();
-                        }
-                    }
-                }
-            } else {
-                return high_score;
+            let record = play_from(&mut tester, &mut test_game, Report::Progress(10));
+            if record.stats.score > high_score {
+                high_score = record.stats.score;
+                println!("New high score: {}", high_score);
+            }
+            if tester.stopped() {
+                break;
+            }
+            games_played += 1;
+
+            let game = training_game(config, seed.map(|seed| seed.wrapping_add(games_played as u64)));
+            tester.train(game, ToGameEnd::new(FixedIterations::new(num_iter)));
+            if games_played >= num_games || tester.stopped() {
+                break;
+            }
+            test_game.reset();
+        }
+
+        if let Some(reason) = tester.budget.lock().unwrap().stopped() {
+            println!("Stopped training: {}", reason);
+        }
+        (tester.trainer, high_score, tester.trained)
+    }
+
+    // Plays a test game with the learned values, first training for
+    // `num_iter` games from any board that has none, and stops once Ctrl-C
+    // or the budget asks. Counts the steps trained.
+    struct Tester<'a, 'h> {
+        trainer: &'a mut AgentTrainer<LearningState>,
+        hyperparameters: &'h Hyperparameters,
+        num_iter: u32,
+        budget: Arc<Mutex<Budget>>,
+        // Only ever taken while training
+        progress: Option<Progress>,
+        trained: u64,
+    }
+
+    impl Tester<'_, '_> {
+        fn train<T: TerminationStrategy<LearningState>>(&mut self, game: Game, termination: T) {
+            let progress = self.progress.take().expect("progress is put back after training");
+            let mut agent = MyAgent::new(game, progress, self.hyperparameters.evaluation());
+            let mut termination = Counted::new(Budgeted::new(termination, self.budget.clone()));
+            train_with(self.trainer, self.hyperparameters, &mut agent, &mut termination);
+            self.trained += termination.steps;
+            agent.progress.episodes.clear();
+            self.progress = Some(agent.progress);
+        }
+
+        fn stopped(&self) -> bool {
+            stop_requested() || self.budget.lock().unwrap().stopped().is_some()
+        }
+    }
+
+    impl Player for Tester<'_, '_> {
+        fn choose(&mut self, game: &Game) -> Action {
+            if self.trainer.best_action(&LearningState::from_board(game.board.clone())).is_none() {
+                // The test game is never undone either
+                self.train(game.clone(), NumGames::new(self.num_iter));
+            }
+            (&*self.trainer).choose(game)
+        }
+
+        fn turn(&mut self, game: &Game) -> Turn {
+            if self.stopped() {
+                return Turn::Stop;
             }
+            Turn::Move(self.choose(game))
         }
     }
 
-    fn _log(message: String) {
-        let mut file = fs::OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open("Results".to_owned() + ".log")
-            .unwrap();
-        file.write(message.as_bytes()).unwrap();
-        println!("{:?}", message);
+    /// Follows the learned values, falling back to the first valid move in
+    /// a state the trainer has never seen.
+    impl Player for AgentTrainer<LearningState> {
+        fn choose(&mut self, game: &Game) -> Action {
+            (&*self).choose(game)
+        }
     }
 
+    /// Lets threads share one trainer when playing.
+    impl Player for &AgentTrainer<LearningState> {
+        fn choose(&mut self, game: &Game) -> Action {
+            self.best_action(&LearningState::from_board(game.board.clone()))
+                .or_else(|| game.get_valid_actions().into_iter().next())
+                .expect("choose is only called while a move is left")
+        }
+    }
+
+    // Stops as the `target_games`th game ends.
     struct NumGames {
         curr_game: u32,
         target_games: u32,
@@ -199,19 +621,162 @@ pub mod ai {
 
     impl NumGames {
         pub fn new(target_games: u32) -> NumGames {
-            return NumGames {
+            NumGames {
                 curr_game: 0,
-                target_games: target_games,
-            };
+                target_games,
+            }
         }
     }
 
-    impl<S: State> TerminationStrategy<S> for NumGames {
-        fn should_stop(&mut self, state: &S) -> bool {
-            if state.reward() < 0.0 {
+    impl TerminationStrategy<LearningState> for NumGames {
+        fn should_stop(&mut self, state: &LearningState) -> bool {
+            if state.is_terminal() {
                 self.curr_game += 1;
             }
-            return self.curr_game == self.target_games;
+            self.curr_game >= self.target_games
+        }
+    }
+
+    // Lets another strategy stop training only as a game ends, so every run
+    // plays whole games and each of them reaches the budget and the metrics.
+    struct ToGameEnd<T> {
+        inner: T,
+        done: bool,
+    }
+
+    impl<T> ToGameEnd<T> {
+        fn new(inner: T) -> ToGameEnd<T> {
+            ToGameEnd { inner, done: false }
+        }
+    }
+
+    impl<T: TerminationStrategy<LearningState>> TerminationStrategy<LearningState> for ToGameEnd<T> {
+        fn should_stop(&mut self, state: &LearningState) -> bool {
+            self.done = self.done || self.inner.should_stop(state);
+            self.done && state.is_terminal()
+        }
+    }
+
+    // Stops another strategy early once a finished game has used up the
+    // budget, at the end of the game in play. The agent counts the games.
+    struct Budgeted<T> {
+        inner: T,
+        budget: Arc<Mutex<Budget>>,
+    }
+
+    impl<T> Budgeted<T> {
+        fn new(inner: T, budget: Arc<Mutex<Budget>>) -> Budgeted<T> {
+            Budgeted { inner, budget }
+        }
+    }
+
+    impl<T: TerminationStrategy<LearningState>> TerminationStrategy<LearningState> for Budgeted<T> {
+        fn should_stop(&mut self, state: &LearningState) -> bool {
+            let out_of_budget = state.is_terminal() && self.budget.lock().unwrap().stopped().is_some();
+            self.inner.should_stop(state) || out_of_budget
+        }
+    }
+
+    // Counts the training steps another strategy lets through, for the model's metadata.
+    struct Counted<T> {
+        inner: T,
+        steps: u64,
+    }
+
+    impl<T> Counted<T> {
+        fn new(inner: T) -> Counted<T> {
+            Counted { inner, steps: 0 }
+        }
+    }
+
+    impl<S: State, T: TerminationStrategy<S>> TerminationStrategy<S> for Counted<T> {
+        fn should_stop(&mut self, state: &S) -> bool {
+            self.steps += 1;
+            self.inner.should_stop(state)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::heuristic::heuristic::Reward;
+
+        fn state(tile: u32) -> LearningState {
+            LearningState::from_board(vec![vec![tile, 0], vec![0, 0]].into())
+        }
+
+        #[test]
+        fn counts_whole_games_to_the_end() {
+            let mut game = Game::with_config_and_seed(GameConfig::with_size(2, 2), 3);
+            game.set_history_depth(0);
+            let progress = Progress::untracked(&Exploration::Random, &Limits::episodes(2));
+            let budget = progress.budget.clone();
+            let mut agent = MyAgent::new(game, progress, Hyperparameters::default().evaluation());
+            let mut termination = Counted::new(Budgeted::new(FixedIterations::new(u32::MAX), budget.clone()));
+            train_with(&mut AgentTrainer::new(), &Hyperparameters::default(), &mut agent, &mut termination);
+
+            // Stopped on the second game's last move, not after a reset
+            assert!(agent.state.is_terminal());
+            assert_eq!(agent.progress.episodes.len(), 2);
+            assert_eq!(budget.lock().unwrap().episodes(), 2);
+            let steps: u32 = agent.progress.episodes.iter().map(|episode| episode.steps).sum();
+            // One step per move, plus the one that started the second game
+            assert_eq!(termination.steps, steps as u64 + 1);
+        }
+
+        #[test]
+        fn rewards_follow_the_chosen_heuristic() {
+            let mut game = Game::with_seed(6);
+            game.board = vec![vec![2, 2, 0, 0], vec![0; 4], vec![0; 4], vec![0; 4]].into();
+            game.action(&Action::MergeLeft);
+
+            let hyperparameters = Hyperparameters {
+                reward: Reward::Score,
+                ..Hyperparameters::default()
+            };
+            assert_eq!(LearningState::new(&game, hyperparameters.evaluation()).reward, 4.0);
+            let shaped = LearningState::new(&game, Hyperparameters::default().evaluation());
+            assert_ne!(shaped.reward, 4.0);
+        }
+
+        #[test]
+        fn runs_end_with_their_game() {
+            let config = GameConfig::with_size(2, 2);
+            let schedule = Schedule {
+                iterations: 1,
+                runs: Some(1),
+                limits: Limits::default(),
+                threads: 1,
+                checkpoint_every: 0,
+                seed: Some(3),
+            };
+            let progress = Progress::untracked(&Exploration::Random, &schedule.limits);
+            let budget = progress.budget.clone();
+            let progress = train_run(&mut AgentTrainer::new(), &Hyperparameters::default(), &schedule, &config, progress, 0);
+
+            // One step in, the run still plays its game out
+            assert_eq!(progress.episodes.len(), 1);
+            assert!(progress.episodes[0].steps > 1);
+            assert_eq!(progress.steps, progress.episodes[0].steps as u64);
+            assert_eq!(budget.lock().unwrap().episodes(), 1);
+        }
+
+        #[test]
+        fn averages_only_what_workers_changed() {
+            let mut base = Values::new();
+            base.entry(state(2)).or_default().insert(Action::MergeLeft, 1.0);
+            base.entry(state(2)).or_default().insert(Action::MergeUp, 5.0);
+
+            let mut first = base.clone();
+            first.get_mut(&state(2)).unwrap().insert(Action::MergeLeft, 3.0);
+            first.entry(state(4)).or_default().insert(Action::MergeDown, 2.0);
+            let mut second = base.clone();
+            second.get_mut(&state(2)).unwrap().insert(Action::MergeLeft, 5.0);
+
+            let merged = average_changes(base, vec![first, second]);
+            assert_eq!(merged[&state(2)][&Action::MergeLeft], 4.0);
+            assert_eq!(merged[&state(2)][&Action::MergeUp], 5.0);
+            assert_eq!(merged[&state(4)][&Action::MergeDown], 2.0);
         }
     }
 }
@@ -0,0 +1,346 @@
+pub mod bitboard {
+    //! A 4x4 board packed into a `u64`, one 4-bit tile exponent per cell, with
+    //! every move looked up in tables precomputed for all 65536 possible rows.
+    //! Cell (row, col) lives in bits `16 * row + 4 * col ..`, an empty cell is 0
+    //! and a tile of value `2^e` is stored as `e`.
+    use std::sync::OnceLock;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::game::oxydized2048::Action;
+
+    /// Largest tile `from_rows` accepts. Any merge of these still fits in a
+    /// nibble (2^15), so moves on a converted board are always exact.
+    pub const MAX_EXACT_TILE: u32 = 1 << 14;
+
+    const ROW_MASK: u64 = 0xFFFF;
+
+    /// The exponent a tile is packed as, or None when `from_rows` would reject it.
+    pub fn exponent_of(value: u32) -> Option<u8> {
+        match value {
+            0 => Some(0),
+            v if v.is_power_of_two() && v > 1 && v <= MAX_EXACT_TILE => Some(v.trailing_zeros() as u8),
+            _ => None,
+        }
+    }
+
+    struct Tables {
+        left: Vec<u16>,
+        right: Vec<u16>,
+        score_left: Vec<u32>,
+        score_right: Vec<u32>,
+    }
+
+    fn tables() -> &'static Tables {
+        static TABLES: OnceLock<Tables> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let mut tables = Tables {
+                left: vec![0; 1 << 16],
+                right: vec![0; 1 << 16],
+                score_left: vec![0; 1 << 16],
+                score_right: vec![0; 1 << 16],
+            };
+            for row in 0..=u16::MAX {
+                let (moved, score) = slide_row(row);
+                tables.left[row as usize] = moved;
+                tables.score_left[row as usize] = score;
+
+                let reversed = reverse_row(row);
+                let (moved, score) = slide_row(reversed);
+                tables.right[row as usize] = reverse_row(moved);
+                tables.score_right[row as usize] = score;
+            }
+            tables
+        })
+    }
+
+    // Slides one packed row toward column 0 with the usual rules: tiles compact
+    // and each merges at most once. Two 2^15 tiles would overflow the nibble, so
+    // that merge saturates at 2^15; `from_rows` never produces such a row.
+    fn slide_row(row: u16) -> (u16, u32) {
+        let mut line = [0_u16; 4];
+        let mut next = 0;
+        let mut can_merge = false;
+        let mut score = 0;
+        for col in 0..4 {
+            let exponent = (row >> (4 * col)) & 0xF;
+            if exponent == 0 {
+                continue;
+            }
+            if can_merge && line[next - 1] == exponent {
+                line[next - 1] = (exponent + 1).min(15);
+                score += 1 << line[next - 1];
+                can_merge = false;
+            } else {
+                line[next] = exponent;
+                next += 1;
+                can_merge = true;
+            }
+        }
+        let moved = line
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (col, exponent)| acc | (exponent << (4 * col)));
+        (moved, score)
+    }
+
+    fn reverse_row(row: u16) -> u16 {
+        (row >> 12) | ((row >> 4) & 0x00F0) | ((row << 4) & 0x0F00) | (row << 12)
+    }
+
+    // Swaps rows and columns, so column moves can reuse the row tables.
+    fn transpose(x: u64) -> u64 {
+        let a1 = x & 0xF0F0_0F0F_F0F0_0F0F;
+        let a2 = x & 0x0000_F0F0_0000_F0F0;
+        let a3 = x & 0x0F0F_0000_0F0F_0000;
+        let a = a1 | (a2 << 12) | (a3 >> 12);
+        let b1 = a & 0xFF00_FF00_00FF_00FF;
+        let b2 = a & 0x00FF_00FF_0000_0000;
+        let b3 = a & 0x0000_0000_FF00_FF00;
+        b1 | (b2 >> 24) | (b3 << 24)
+    }
+
+    fn shift_rows(board: u64, moves: &[u16], scores: &[u32]) -> (u64, u32) {
+        let mut moved = 0;
+        let mut score = 0;
+        for row in 0..4 {
+            let bits = ((board >> (16 * row)) & ROW_MASK) as usize;
+            moved |= (moves[bits] as u64) << (16 * row);
+            score += scores[bits];
+        }
+        (moved, score)
+    }
+
+    #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+    pub struct Bitboard(pub u64);
+
+    impl Bitboard {
+        /// Packs a 4x4 board, or returns None when it is another size or holds a
+        /// tile that is not a power of two up to `MAX_EXACT_TILE`.
+        pub fn from_rows(rows: &[Vec<u32>]) -> Option<Bitboard> {
+            if rows.len() != 4 || rows.iter().any(|row| row.len() != 4) {
+                return None;
+            }
+            let mut bits = 0;
+            for (i, value) in rows.iter().flatten().enumerate() {
+                bits |= (exponent_of(*value)? as u64) << (4 * i);
+            }
+            Some(Bitboard(bits))
+        }
+
+        pub fn to_rows(self) -> Vec<Vec<u32>> {
+            (0..4)
+                .map(|row| (0..4).map(|col| self.tile(row * 4 + col)).collect())
+                .collect()
+        }
+
+        /// The exponent stored in `cell` (`row * 4 + col`), 0 when empty.
+        pub fn exponent(self, cell: usize) -> u8 {
+            ((self.0 >> (4 * cell)) & 0xF) as u8
+        }
+
+        /// The tile value in `cell` (`row * 4 + col`), 0 when empty.
+        pub fn tile(self, cell: usize) -> u32 {
+            match self.exponent(cell) {
+                0 => 0,
+                exponent => 1 << exponent,
+            }
+        }
+
+        /// Places a tile of value `2^exponent` in `cell`, replacing what was there.
+        pub fn with_exponent(self, cell: usize, exponent: u8) -> Bitboard {
+            let cleared = self.0 & !(0xF << (4 * cell));
+            Bitboard(cleared | ((exponent as u64 & 0xF) << (4 * cell)))
+        }
+
+        /// Returns the board after `action` and the score it earned.
+        pub fn shift(self, action: &Action) -> (Bitboard, u32) {
+            let tables = tables();
+            let (moved, score) = match action {
+                Action::MergeLeft => shift_rows(self.0, &tables.left, &tables.score_left),
+                Action::MergeRight => shift_rows(self.0, &tables.right, &tables.score_right),
+                Action::MergeUp => {
+                    let (moved, score) = shift_rows(transpose(self.0), &tables.left, &tables.score_left);
+                    (transpose(moved), score)
+                }
+                Action::MergeDown => {
+                    let (moved, score) = shift_rows(transpose(self.0), &tables.right, &tables.score_right);
+                    (transpose(moved), score)
+                }
+            };
+            (Bitboard(moved), score)
+        }
+
+        /// Actions that change the board, in the same order as `Game::get_valid_actions`.
+        pub fn valid_actions(self) -> Vec<Action> {
+            [Action::MergeUp, Action::MergeDown, Action::MergeRight, Action::MergeLeft]
+                .into_iter()
+                .filter(|action| self.shift(action).0 != self)
+                .collect()
+        }
+
+        pub fn is_gameover(self) -> bool {
+            if self.empty_cells() > 0 {
+                return false;
+            }
+            let tables = tables();
+            let transposed = transpose(self.0);
+            (0..4).all(|row| {
+                let rows = ((self.0 >> (16 * row)) & ROW_MASK) as usize;
+                let cols = ((transposed >> (16 * row)) & ROW_MASK) as usize;
+                tables.left[rows] as usize == rows && tables.left[cols] as usize == cols
+            })
+        }
+
+        pub fn empty_cells(self) -> u32 {
+            // Fold each nibble down to its lowest bit: set iff the cell is occupied
+            let mut x = self.0;
+            x |= (x >> 2) & 0x3333_3333_3333_3333;
+            x |= x >> 1;
+            16 - (x & 0x1111_1111_1111_1111).count_ones()
+        }
+
+        /// Cells (`row * 4 + col`) with no tile in them.
+        pub fn empty_positions(self) -> impl Iterator<Item = usize> {
+            (0..16).filter(move |cell| self.exponent(*cell) == 0)
+        }
+
+        pub fn max_tile(self) -> u32 {
+            (0..16).map(|cell| self.tile(cell)).max().unwrap_or(0)
+        }
+
+        /// The four cells of `col`, top to bottom, as a packed row.
+        pub fn column(self, col: usize) -> u16 {
+            ((transpose(self.0) >> (16 * col)) & ROW_MASK) as u16
+        }
+
+        pub fn row(self, row: usize) -> u16 {
+            ((self.0 >> (16 * row)) & ROW_MASK) as u16
+        }
+
+        /// The board reflected across its main diagonal.
+        pub fn transpose(self) -> Bitboard {
+            Bitboard(transpose(self.0))
+        }
+
+        /// The board reflected left to right.
+        pub fn mirror(self) -> Bitboard {
+            let mut bits = 0;
+            for row in 0..4 {
+                bits |= (reverse_row(self.row(row)) as u64) << (16 * row);
+            }
+            Bitboard(bits)
+        }
+
+        /// All eight rotations and reflections of the board, itself first.
+        pub fn symmetries(self) -> [Bitboard; 8] {
+            let mirrored = self.mirror();
+            let transposed = self.transpose();
+            [
+                self,
+                mirrored,
+                transposed,
+                transposed.mirror(),
+                mirrored.transpose(),
+                transposed.mirror().transpose(),
+                mirrored.transpose().mirror(),
+                transposed.mirror().transpose().mirror(),
+            ]
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn packs_and_unpacks_rows() {
+            let rows = vec![
+                vec![0, 2, 4, 8],
+                vec![16, 32, 64, 128],
+                vec![256, 512, 1024, 2048],
+                vec![4096, 8192, 16384, 0],
+            ];
+            let board = Bitboard::from_rows(&rows).unwrap();
+            assert_eq!(board.to_rows(), rows);
+            assert_eq!(board.empty_cells(), 2);
+            assert_eq!(board.max_tile(), 16384);
+            assert_eq!(board.exponent(5), 5);
+
+            assert!(Bitboard::from_rows(&[vec![0; 5], vec![0; 5], vec![0; 5], vec![0; 5]]).is_none());
+            assert!(Bitboard::from_rows(&[vec![3, 0, 0, 0], vec![0; 4], vec![0; 4], vec![0; 4]]).is_none());
+            assert!(Bitboard::from_rows(&[vec![32768, 0, 0, 0], vec![0; 4], vec![0; 4], vec![0; 4]]).is_none());
+        }
+
+        #[test]
+        fn transpose_swaps_rows_and_columns() {
+            let board = Bitboard(0xFEDC_BA98_7654_3210);
+            let transposed = Bitboard(transpose(board.0));
+            for row in 0..4 {
+                for col in 0..4 {
+                    assert_eq!(transposed.exponent(col * 4 + row), board.exponent(row * 4 + col));
+                }
+            }
+            assert_eq!(transpose(transposed.0), board.0);
+        }
+
+        #[test]
+        fn symmetries_are_distinct_rearrangements() {
+            let board = Bitboard(0x0000_0000_0000_4321);
+            let symmetries = board.symmetries();
+            for (i, a) in symmetries.iter().enumerate() {
+                let mut exponents: Vec<u8> = (0..16).map(|cell| a.exponent(cell)).collect();
+                exponents.sort();
+                assert_eq!(exponents, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4]);
+                assert!(symmetries[i + 1..].iter().all(|b| a != b));
+            }
+            assert_eq!(board.mirror().row(0), 0x1234);
+            assert_eq!(board.transpose().column(0), 0x4321);
+        }
+
+        #[test]
+        fn shifts_in_every_direction() {
+            let board = Bitboard::from_rows(&[
+                vec![2, 2, 4, 0],
+                vec![0, 0, 0, 0],
+                vec![2, 0, 0, 0],
+                vec![4, 0, 0, 4],
+            ])
+            .unwrap();
+
+            let (left, score) = board.shift(&Action::MergeLeft);
+            assert_eq!(left.to_rows()[0], vec![4, 4, 0, 0]);
+            assert_eq!(left.to_rows()[3], vec![8, 0, 0, 0]);
+            assert_eq!(score, 12);
+
+            let (right, _) = board.shift(&Action::MergeRight);
+            assert_eq!(right.to_rows()[0], vec![0, 0, 4, 4]);
+
+            let (up, score) = board.shift(&Action::MergeUp);
+            assert_eq!(up.to_rows()[0], vec![4, 2, 4, 4]);
+            assert_eq!(up.to_rows()[1], vec![4, 0, 0, 0]);
+            assert_eq!(score, 4);
+
+            let (down, _) = board.shift(&Action::MergeDown);
+            assert_eq!(down.to_rows()[3], vec![4, 2, 4, 4]);
+            assert_eq!(down.to_rows()[2], vec![4, 0, 0, 0]);
+        }
+
+        #[test]
+        fn detects_game_over() {
+            let full = Bitboard::from_rows(&[
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+            ])
+            .unwrap();
+            assert!(full.is_gameover());
+            assert!(full.valid_actions().is_empty());
+
+            let mergeable = full.with_exponent(15, 2);
+            assert!(!mergeable.is_gameover());
+            assert_eq!(mergeable.valid_actions().len(), 4);
+        }
+    }
+}
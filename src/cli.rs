@@ -0,0 +1,910 @@
+pub mod cli {
+    //! Subcommands for running everything the menu offers without prompts, so
+    //! training and evaluation can be scripted. With no subcommand the
+    //! interactive menu runs instead.
+    use std::error::Error;
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use clap::{Args, Parser, Subcommand, ValueEnum};
+    use rurel::AgentTrainer;
+
+    use crate::ai::ai::{self, LearningState, Schedule, LEARNED_STATE};
+    use crate::evaluation::evaluation::{self, Evaluation};
+    use crate::expectimax::expectimax::Expectimax;
+    use crate::exploration::exploration::{Decay, Exploration, Shape};
+    use crate::game::oxydized2048::{Game, GameConfig};
+    use crate::heuristic::heuristic::{self, Reward};
+    use crate::metrics::metrics::MetricsLog;
+    use crate::model::model::{load_legacy, Format, Hyperparameters, Metadata, Model, ModelError, ModelStore};
+    use crate::montecarlo::montecarlo::{MonteCarlo, Rollouts};
+    use crate::ntuple::ntuple::{self, NTupleNetwork};
+    use crate::player::player::{GameRecord, Player, Random, Report};
+    use crate::termination::termination::Limits;
+    use crate::ui::ui;
+
+    #[derive(Parser, Debug)]
+    #[command(name = "rusty2048", about = "2048 in the terminal, and agents that learn to play it")]
+    pub struct Cli {
+        #[command(subcommand)]
+        pub command: Option<Command>,
+    }
+
+    #[derive(Subcommand, Debug)]
+    pub enum Command {
+        /// Play in the full-screen terminal UI
+        Play {
+            #[command(flatten)]
+            board: BoardArgs,
+        },
+        /// Train a model and save it
+        Train {
+            #[command(flatten)]
+            board: BoardArgs,
+            #[command(flatten)]
+            learning: LearningArgs,
+            #[arg(long, value_enum, default_value_t = Agent::QLearning)]
+            agent: Agent,
+            #[command(flatten)]
+            stop: StopArgs,
+            /// Training runs of `--iterations` steps and the rest of the game
+            /// in play, 1000 unless another limit is set (Q-learning)
+            #[arg(long)]
+            runs: Option<u32>,
+            /// Model file, defaulting to the agent's usual file
+            #[arg(long)]
+            model: Option<PathBuf>,
+            /// Start over instead of resuming from an existing model
+            #[arg(long)]
+            fresh: bool,
+            /// Save the model every this many runs, 0 for only at the end (Q-learning)
+            #[arg(long, default_value_t = 100)]
+            checkpoint_every: u32,
+            /// Workers training at once, each on its own copy of the Q-table;
+            /// the copies are averaged at every checkpoint (Q-learning)
+            #[arg(long, default_value_t = 1)]
+            threads: usize,
+            /// Append each training game's metrics here, as .csv or .jsonl (Q-learning)
+            #[arg(long, value_parser = parse_metrics_path)]
+            metrics: Option<PathBuf>,
+            /// Games the printed moving averages are taken over (Q-learning).
+            /// With several threads they print at each checkpoint, so only at
+            /// the end with `--checkpoint-every 0`
+            #[arg(long, default_value_t = 100)]
+            metrics_window: usize,
+        },
+        /// Play games with the learned Q-table
+        Test {
+            #[command(flatten)]
+            board: BoardArgs,
+            #[command(flatten)]
+            games: GamesArgs,
+            /// Model file, as .ron, .json, .bin or .qtable
+            #[arg(long, default_value = LEARNED_STATE)]
+            model: PathBuf,
+        },
+        /// Alternate testing the learned Q-table and retraining it after each loss
+        TrainTest {
+            #[command(flatten)]
+            board: BoardArgs,
+            #[command(flatten)]
+            learning: LearningArgs,
+            #[command(flatten)]
+            stop: StopArgs,
+            /// Test games to lose, retraining after each, before stopping
+            #[arg(long, default_value_t = 1)]
+            games: u32,
+            /// Model file, as .ron, .json, .bin or .qtable
+            #[arg(long, default_value = LEARNED_STATE)]
+            model: PathBuf,
+        },
+        /// Play games quietly with any player and summarise the results
+        Bench {
+            #[command(flatten)]
+            board: BoardArgs,
+            #[command(flatten)]
+            games: GamesArgs,
+            #[arg(long, value_enum, default_value_t = PlayerKind::Expectimax)]
+            player: PlayerKind,
+            /// Model file for the q-learning and ntuple players
+            #[arg(long)]
+            model: Option<PathBuf>,
+            /// Expectimax search depth in moves
+            #[arg(long, default_value_t = 2)]
+            depth: u32,
+            /// Expectimax board heuristic
+            #[arg(long, default_value = "monotonic", value_parser = heuristic::NAMES)]
+            heuristic: String,
+            /// Monte Carlo playouts per action
+            #[arg(long, default_value_t = 100, conflicts_with = "millis")]
+            playouts: u32,
+            /// Monte Carlo time per move in milliseconds, instead of a playout count
+            #[arg(long)]
+            millis: Option<u64>,
+            /// Monte Carlo playout threads per game, defaulting to the cores
+            /// left over by `--threads`
+            #[arg(long)]
+            playout_threads: Option<usize>,
+        },
+        /// Copy a Q-table into another format, e.g. `learned_state.ron` to a
+        /// compact `.qtable`, merging any board stored more than once. Also
+        /// migrates `learned_state.ron` files from older versions.
+        Convert {
+            input: PathBuf,
+            output: PathBuf,
+        },
+        /// Step through a game saved with `--record`
+        Replay {
+            file: PathBuf,
+            /// Pause between moves in milliseconds
+            #[arg(long, default_value_t = 200)]
+            delay: u64,
+        },
+    }
+
+    #[derive(Args, Debug)]
+    pub struct BoardArgs {
+        /// Board size as rows x cols
+        #[arg(long, default_value = "4x4", value_parser = parse_size)]
+        pub size: (usize, usize),
+        /// Seed for dealing games, for repeatable runs
+        #[arg(long)]
+        pub seed: Option<u64>,
+    }
+
+    impl BoardArgs {
+        pub fn config(&self) -> GameConfig {
+            GameConfig::with_size(self.size.0, self.size.1)
+        }
+    }
+
+    #[derive(Args, Debug)]
+    pub struct GamesArgs {
+        /// Games to play
+        #[arg(long, default_value_t = 1000)]
+        pub games: u32,
+        /// Save the best game here for `replay`, as .ron, .json or .bin
+        #[arg(long, value_parser = parse_record_path)]
+        pub record: Option<PathBuf>,
+        /// Save the results here, as .json with every game or a .csv row of
+        /// the summary appended to the file
+        #[arg(long, value_parser = parse_report_path)]
+        pub report: Option<PathBuf>,
+        /// Games played at once, defaulting to one per core
+        #[arg(long)]
+        pub threads: Option<usize>,
+    }
+
+    impl GamesArgs {
+        pub fn threads(&self) -> usize {
+            self.threads.unwrap_or_else(evaluation::available_threads).max(1)
+        }
+    }
+
+    /// Q-learning hyperparameters, shared with the menu.
+    #[derive(Args, Debug, Clone, PartialEq)]
+    pub struct LearningArgs {
+        /// Learning rate (n-tuple network: defaults to 0.1)
+        #[arg(long)]
+        pub alpha: Option<f64>,
+        /// Discount factor
+        #[arg(long, default_value_t = 0.6)]
+        pub gamma: f64,
+        /// Value of actions not tried yet
+        #[arg(long, default_value_t = 0.5)]
+        pub initial_value: f64,
+        /// Steps per training run, which then plays its game out
+        #[arg(long, default_value_t = 10000)]
+        pub iterations: u32,
+        /// How training picks its moves (Q-learning)
+        #[arg(long, value_enum, default_value_t = ExplorationKind::Random)]
+        pub exploration: ExplorationKind,
+        /// Chance of a random move when epsilon-greedy training starts
+        #[arg(long, default_value_t = 1.0)]
+        pub epsilon: f64,
+        /// Chance of a random move once epsilon has decayed
+        #[arg(long, default_value_t = 0.05)]
+        pub epsilon_end: f64,
+        /// Softmax temperature when training starts, in units of reward
+        #[arg(long, default_value_t = 100.0)]
+        pub temperature: f64,
+        /// Softmax temperature once it has decayed
+        #[arg(long, default_value_t = 1.0)]
+        pub temperature_end: f64,
+        /// How epsilon or the temperature goes from its start to its end
+        #[arg(long, value_enum, default_value_t = DecayShape::Linear)]
+        pub decay: DecayShape,
+        /// Training steps epsilon or the temperature takes to reach its end
+        #[arg(long, default_value_t = 1_000_000)]
+        pub decay_steps: u64,
+        /// Weight of the UCB bonus for moves tried least
+        #[arg(long, default_value_t = 10.0)]
+        pub ucb_c: f64,
+        /// Heuristic whose change across a move is the reward (Q-learning).
+        /// Defaults to the one a resumed model was trained with, else shaped
+        #[arg(long, value_enum)]
+        pub reward: Option<Reward>,
+    }
+
+    impl LearningArgs {
+        pub fn q_alpha(&self) -> f64 {
+            self.alpha.unwrap_or(0.2)
+        }
+
+        pub fn hyperparameters(&self) -> Result<Hyperparameters, String> {
+            Ok(Hyperparameters {
+                alpha: self.q_alpha(),
+                gamma: self.gamma,
+                initial_value: self.initial_value,
+                exploration: self.exploration()?,
+                reward: self.reward.unwrap_or_default(),
+            })
+        }
+
+        /// Like `hyperparameters`, but keeps the reward `saved` was trained
+        /// with unless `--reward` picks another.
+        pub fn resumed(&self, saved: &Hyperparameters) -> Result<Hyperparameters, String> {
+            Ok(Hyperparameters {
+                reward: self.reward.unwrap_or(saved.reward),
+                ..self.hyperparameters()?
+            })
+        }
+
+        pub fn exploration(&self) -> Result<Exploration, String> {
+            let decay = |start: f64, end: f64, name: &str| {
+                let shape = match self.decay {
+                    DecayShape::Constant => Shape::Constant,
+                    DecayShape::Linear => Shape::Linear,
+                    DecayShape::Exponential => Shape::Exponential,
+                };
+                if shape == Shape::Exponential && (start <= 0.0 || end <= 0.0) {
+                    return Err(format!("an exponential decay needs the {} to stay above 0", name));
+                }
+                Ok(Decay {
+                    start,
+                    end,
+                    steps: self.decay_steps,
+                    shape,
+                })
+            };
+            Ok(match self.exploration {
+                ExplorationKind::Random => Exploration::Random,
+                ExplorationKind::EpsilonGreedy => {
+                    if ![self.epsilon, self.epsilon_end].iter().all(|epsilon| (0.0..=1.0).contains(epsilon)) {
+                        return Err("epsilon is a probability, from 0 to 1".to_owned());
+                    }
+                    Exploration::EpsilonGreedy(decay(self.epsilon, self.epsilon_end, "epsilon")?)
+                }
+                ExplorationKind::Softmax => {
+                    Exploration::Softmax(decay(self.temperature, self.temperature_end, "temperature")?)
+                }
+                ExplorationKind::Ucb => Exploration::Ucb { c: self.ucb_c },
+            })
+        }
+    }
+
+    impl Default for LearningArgs {
+        fn default() -> LearningArgs {
+            LearningArgs {
+                alpha: None,
+                gamma: 0.6,
+                initial_value: 0.5,
+                iterations: 10000,
+                exploration: ExplorationKind::Random,
+                epsilon: 1.0,
+                epsilon_end: 0.05,
+                temperature: 100.0,
+                temperature_end: 1.0,
+                decay: DecayShape::Linear,
+                decay_steps: 1_000_000,
+                ucb_c: 10.0,
+                reward: None,
+            }
+        }
+    }
+
+    /// When training stops, judged as each training game ends.
+    #[derive(Args, Debug, Clone, PartialEq)]
+    pub struct StopArgs {
+        /// Stop after this many training games (n-tuple network: defaults to
+        /// 10000 when no other limit is set)
+        #[arg(long)]
+        pub episodes: Option<u64>,
+        /// Stop after this long, like 90s, 45m or 2h
+        #[arg(long, value_parser = parse_duration)]
+        pub time_limit: Option<Duration>,
+        /// Stop once the mean score of the last `--stop-window` games reaches this
+        #[arg(long)]
+        pub target_score: Option<f64>,
+        /// Stop once that mean score has gone this many games without a new best
+        #[arg(long)]
+        pub plateau: Option<u64>,
+        /// Games `--target-score` and `--plateau` take the mean over
+        #[arg(long, default_value_t = 100)]
+        pub stop_window: usize,
+    }
+
+    impl StopArgs {
+        pub fn limits(&self) -> Limits {
+            Limits {
+                episodes: self.episodes,
+                time: self.time_limit,
+                target_score: self.target_score,
+                patience: self.plateau,
+                window: self.stop_window,
+            }
+        }
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ExplorationKind {
+        Random,
+        EpsilonGreedy,
+        Softmax,
+        Ucb,
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DecayShape {
+        Constant,
+        Linear,
+        Exponential,
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Agent {
+        QLearning,
+        Ntuple,
+    }
+
+    #[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum PlayerKind {
+        QLearning,
+        Expectimax,
+        MonteCarlo,
+        Ntuple,
+        Random,
+    }
+
+    /// Parses `4x4`, `3x6` or just `5`; both sides must be at least 2.
+    pub fn parse_board_size(size: &str) -> Option<(usize, usize)> {
+        let mut dims = size.trim().split(['x', 'X']).map(|dim| dim.trim().parse::<usize>());
+        let rows = dims.next()?.ok()?;
+        let cols = match dims.next() {
+            Some(cols) => cols.ok()?,
+            None => rows,
+        };
+        if dims.next().is_some() || rows < 2 || cols < 2 {
+            return None;
+        }
+        Some((rows, cols))
+    }
+
+    fn parse_size(size: &str) -> Result<(usize, usize), String> {
+        parse_board_size(size).ok_or_else(|| "expected a size like 4x4, 5x5 or 3x6".to_owned())
+    }
+
+    /// Parses `90s`, `45m`, `2h` or a bare number of seconds.
+    pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+        let duration = duration.trim();
+        let (number, unit) = match duration.find(|c: char| c.is_ascii_alphabetic()) {
+            Some(split) => duration.split_at(split),
+            None => (duration, "s"),
+        };
+        let seconds = match unit {
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err("expected a duration like 90s, 45m or 2h".to_owned()),
+        };
+        match number.trim().parse::<f64>() {
+            Ok(number) if number >= 0.0 && number.is_finite() => Ok(Duration::from_secs_f64(number * seconds)),
+            _ => Err("expected a duration like 90s, 45m or 2h".to_owned()),
+        }
+    }
+
+    fn parse_report_path(path: &str) -> Result<PathBuf, String> {
+        match path.rsplit_once('.') {
+            Some((_, "json" | "csv")) => Ok(path.into()),
+            _ => Err("expected a .json or .csv file".to_owned()),
+        }
+    }
+
+    fn parse_record_path(path: &str) -> Result<PathBuf, String> {
+        match Format::from_path(Path::new(path)) {
+            Some(Format::Ron | Format::Json | Format::Bincode) => Ok(path.into()),
+            _ => Err("expected a .ron, .json or .bin file".to_owned()),
+        }
+    }
+
+    fn parse_metrics_path(path: &str) -> Result<PathBuf, String> {
+        match path.rsplit_once('.') {
+            Some((_, "csv" | "jsonl")) => Ok(path.into()),
+            _ => Err("expected a .csv or .jsonl file".to_owned()),
+        }
+    }
+
+    // Loads a model, reading a `.ron` file that isn't one as a Q-table from
+    // before models had metadata.
+    fn load_model_or_legacy(store: &ModelStore) -> Result<Model, ModelError> {
+        or_legacy(store, store.load_model(), |model| model)
+    }
+
+    // Falls back to reading a RON file that failed to decode as a legacy Q-table
+    fn or_legacy<T>(store: &ModelStore, loaded: Result<T, ModelError>, from_model: fn(Model) -> T) -> Result<T, ModelError> {
+        match loaded {
+            Err(ModelError::Decode(_, _)) if store.format() == Format::Ron => {
+                println!("Not a current model, reading {} as a legacy Q-table...", store.path().display());
+                load_legacy(store.path()).map(from_model)
+            }
+            loaded => loaded,
+        }
+    }
+
+    /// Loads a Q-table to train or play with, including a legacy
+    /// `learned_state.ron`, which is saved in the current format next time.
+    pub fn load_trainer(store: &ModelStore) -> Result<(AgentTrainer<LearningState>, Metadata), ModelError> {
+        println!("Loading learned state from {}...", store.path().display());
+        let (metadata, values) = or_legacy(store, store.load_learned_values(), Model::into_learned_values)?;
+        println!(
+            "Trained for {} steps since {}",
+            metadata.iterations,
+            metadata.created.format("%Y-%m-%d %H:%M")
+        );
+        let mut trainer = AgentTrainer::new();
+        trainer.import_state(values);
+        Ok((trainer, metadata))
+    }
+
+    pub fn save_trainer(trainer: &AgentTrainer<LearningState>, mut metadata: Metadata, store: &ModelStore) -> Result<(), ModelError> {
+        println!("Saving learned state to {}...", store.path().display());
+        metadata.saved = Utc::now();
+        store.save_model(&Model::new(metadata, trainer.export_learned_values()))
+    }
+
+    /// Trains Q-learning into `store` as `schedule` says, resuming from the
+    /// model there unless `fresh`, and logs every training game to `metrics`.
+    /// Saves at every checkpoint and, if Ctrl-C stops training early, saves
+    /// what was learned so far.
+    pub fn train_q_learning(
+        store: &ModelStore,
+        learning: &LearningArgs,
+        schedule: &Schedule,
+        config: &GameConfig,
+        fresh: bool,
+        metrics: &mut MetricsLog,
+    ) -> Result<(), Box<dyn Error>> {
+        let (mut trainer, mut metadata) = if store.exists() && !fresh {
+            let (trainer, metadata) = load_trainer(store)?;
+            if (metadata.config.rows, metadata.config.cols) != (config.rows, config.cols) {
+                return Err(format!(
+                    "{} was trained on {}x{} boards; pick that size or start over with --fresh",
+                    store.path().display(),
+                    metadata.config.rows,
+                    metadata.config.cols
+                )
+                .into());
+            }
+            (trainer, metadata)
+        } else {
+            (AgentTrainer::new(), Metadata::new(learning.hyperparameters()?, config.clone()))
+        };
+        metadata.hyperparameters = learning.resumed(&metadata.hyperparameters)?;
+        println!("Exploration: {}", metadata.hyperparameters.exploration);
+        let start = metadata.iterations;
+
+        ai::stop_on_interrupt();
+        let (done, steps) = ai::train(
+            &mut trainer,
+            &metadata.hyperparameters,
+            schedule,
+            config,
+            |episode| {
+                if let Err(e) = metrics.record(episode) {
+                    eprintln!("Metrics are no longer logged: {}", e);
+                }
+            },
+            |trainer, _, steps| {
+                let mut checkpoint = metadata.clone();
+                checkpoint.iterations = start + steps;
+                // A failed checkpoint is worth a warning, not the training so far
+                if let Err(e) = save_trainer(trainer, checkpoint, store) {
+                    eprintln!("Checkpoint failed: {}", e);
+                }
+            },
+        );
+        if let Some(runs) = schedule.runs.filter(|runs| done < *runs) {
+            println!("Stopped after {} of {} runs", done, runs);
+        }
+        metadata.iterations = start + steps;
+        save_trainer(&trainer, metadata, store)?;
+        metrics.flush()?;
+        Ok(())
+    }
+
+    // Plays `games` games on `--threads` workers, each with its own
+    // `new_player`, dealing each game from `seed` when there is one. Prints
+    // the summary and saves the best game to `--record` and the results to
+    // `--report`.
+    fn play_games<P: Player, F: Fn(usize) -> P + Sync>(
+        new_player: F,
+        name: &str,
+        board: &BoardArgs,
+        games: &GamesArgs,
+        report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        let config = board.config();
+        let (records, elapsed) =
+            evaluation::play_games(new_player, &config, games.games, board.seed, games.threads(), report);
+        let evaluation = Evaluation::new(name, &config, board.seed, &records, elapsed);
+        println!("{}", evaluation.summary);
+
+        if let Some(path) = &games.report {
+            evaluation.save(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            println!("Saved the report to {}", path.display());
+        }
+        if let Some(path) = &games.record {
+            if let Some(best) = records.iter().max_by_key(|record| record.stats.score) {
+                ModelStore::new(path)?.save(best)?;
+                println!("Saved the best game to {}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    // The network's tuples are laid out for 4x4 boards only
+    fn ntuple_config(board: &BoardArgs) -> Result<GameConfig, String> {
+        match board.size {
+            (4, 4) => Ok(board.config()),
+            (rows, cols) => Err(format!("the n-tuple network only plays 4x4 boards, not {}x{}", rows, cols)),
+        }
+    }
+
+    pub fn run(command: Command) -> Result<(), Box<dyn Error>> {
+        match command {
+            Command::Play { board } => ui::play(board.config(), board.seed)?,
+            Command::Train {
+                board,
+                learning,
+                agent: Agent::QLearning,
+                stop,
+                runs,
+                model,
+                fresh,
+                checkpoint_every,
+                threads,
+                metrics,
+                metrics_window,
+                ..
+            } => {
+                // Fail on a bad path now rather than after hours of training
+                let store = ModelStore::new(model.unwrap_or_else(|| LEARNED_STATE.into()))?;
+                let limits = stop.limits();
+                let schedule = Schedule {
+                    iterations: learning.iterations,
+                    runs: match runs {
+                        None if limits.is_unlimited() => Some(1000),
+                        runs => runs,
+                    },
+                    limits,
+                    threads,
+                    checkpoint_every,
+                    seed: board.seed,
+                };
+                let mut log = MetricsLog::new(metrics.as_deref(), metrics_window)
+                    .map_err(|e| format!("{}: {}", metrics.unwrap_or_default().display(), e))?;
+                println!("Run ID: {}", log.run_id());
+                train_q_learning(&store, &learning, &schedule, &board.config(), fresh, &mut log)?;
+            }
+            Command::Train { board, learning, agent: Agent::Ntuple, stop, runs, model, fresh, threads, metrics, .. } => {
+                let config = ntuple_config(&board)?;
+                if runs.is_some() || threads != 1 || metrics.is_some() {
+                    return Err("--runs, --threads and --metrics only apply to Q-learning".into());
+                }
+                let path = model.unwrap_or_else(|| ntuple::WEIGHTS_FILE.into());
+                let mut network = if path.exists() && !fresh {
+                    println!("Loading weights from {}...", path.display());
+                    NTupleNetwork::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?
+                } else {
+                    NTupleNetwork::new()
+                };
+                let alpha = learning.alpha.unwrap_or(0.1) as f32;
+                let mut limits = stop.limits();
+                if limits.is_unlimited() {
+                    limits.episodes = Some(10000);
+                }
+                ntuple::train(&mut network, alpha, &limits, 1000, &config, board.seed);
+                println!("Saving weights to {}...", path.display());
+                network.save(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+            Command::Test { board, games, model } => {
+                let (trainer, _) = load_trainer(&ModelStore::new(model)?)?;
+                play_games(|_| &trainer, "q-learning", &board, &games, Report::Result)?;
+            }
+            Command::TrainTest { board, learning, stop, games, model } => {
+                let store = ModelStore::new(model)?;
+                let (mut trainer, mut metadata) = load_trainer(&store)?;
+                metadata.hyperparameters = learning.resumed(&metadata.hyperparameters)?;
+                ai::stop_on_interrupt();
+                let (trainer, high_score, steps) = ai::test_and_train(
+                    &mut trainer,
+                    &metadata.hyperparameters,
+                    learning.iterations,
+                    games,
+                    &stop.limits(),
+                    &board.config(),
+                    board.seed,
+                );
+                println!("High score: {}", high_score);
+                metadata.iterations += steps;
+                save_trainer(trainer, metadata, &store)?;
+            }
+            Command::Bench { board, games, player: kind, model, depth, heuristic: heuristic_name, playouts, millis, playout_threads } => {
+                let kind_name = kind.to_possible_value().unwrap();
+                let name = kind_name.get_name();
+                match kind {
+                    PlayerKind::QLearning => {
+                        let store = ModelStore::new(model.unwrap_or_else(|| LEARNED_STATE.into()))?;
+                        let (trainer, _) = load_trainer(&store)?;
+                        play_games(|_| &trainer, name, &board, &games, Report::Quiet)?;
+                    }
+                    PlayerKind::Expectimax => {
+                        let evaluation = heuristic::by_name(&heuristic_name).unwrap();
+                        play_games(|_| Expectimax::new(depth.max(1), evaluation), name, &board, &games, Report::Quiet)?;
+                    }
+                    PlayerKind::MonteCarlo => {
+                        let rollouts = match millis {
+                            Some(millis) => Rollouts::Time(Duration::from_millis(millis.max(1))),
+                            None => Rollouts::Playouts(playouts.max(1)),
+                        };
+                        let threads = playout_threads
+                            .unwrap_or_else(|| evaluation::available_threads() / games.threads())
+                            .max(1);
+                        play_games(|_| MonteCarlo::new(rollouts, threads), name, &board, &games, Report::Quiet)?;
+                    }
+                    PlayerKind::Ntuple => {
+                        ntuple_config(&board)?;
+                        let path = model.unwrap_or_else(|| ntuple::WEIGHTS_FILE.into());
+                        let network = NTupleNetwork::load(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                        play_games(|_| &network, name, &board, &games, Report::Quiet)?;
+                    }
+                    PlayerKind::Random => {
+                        let seed = board.seed.unwrap_or_else(rand::random);
+                        let new_player = |worker| Random::with_seed(seed.wrapping_add(worker as u64));
+                        play_games(new_player, name, &board, &games, Report::Quiet)?;
+                    }
+                }
+            }
+            Command::Convert { input, output } => {
+                let source = ModelStore::new(&input)?;
+                let target = ModelStore::new(output)?;
+                let model = load_model_or_legacy(&source)?;
+                // Older tables can list a board many times; store each once
+                let entries = model.values.len();
+                let model = Model::new(model.metadata.clone(), model.learned_values());
+                target.save_model(&model)?;
+                println!(
+                    "Read {} entries, saved {} states to {}",
+                    entries,
+                    model.values.len(),
+                    target.path().display()
+                );
+            }
+            Command::Replay { file, delay } => {
+                let record: GameRecord = ModelStore::new(&file)?.load()?;
+                record
+                    .config
+                    .validate()
+                    .map_err(|e| format!("{} holds an invalid game: {}", file.display(), e))?;
+                let mut game = Game::with_config_and_seed(record.config.clone(), record.stats.seed);
+                game.set_history_depth(0);
+                game.display();
+                for action in record.moves.iter() {
+                    thread::sleep(Duration::from_millis(delay));
+                    game.action(action);
+                    println!("{:?} | Score: {}", action, game.get_score());
+                    game.display();
+                }
+                if game.stats() != record.stats {
+                    println!("Replay ended differently from the recording: {:?}", game.stats());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use clap::CommandFactory;
+        use crate::model::model::temp_path;
+        use crate::player::player::play_game;
+        use std::fs;
+
+        #[test]
+        fn command_line_is_well_formed() {
+            Cli::command().debug_assert();
+        }
+
+        #[test]
+        fn parses_training_flags() {
+            let cli = Cli::try_parse_from([
+                "rusty2048", "train", "--alpha", "0.1", "--runs", "5", "--size", "5x5", "--seed", "3", "--time-limit", "45m",
+            ])
+            .unwrap();
+            match cli.command {
+                Some(Command::Train { board, learning, agent, stop, runs, .. }) => {
+                    assert_eq!(board.size, (5, 5));
+                    assert_eq!(board.seed, Some(3));
+                    assert_eq!(learning.q_alpha(), 0.1);
+                    assert_eq!(learning.gamma, LearningArgs::default().gamma);
+                    assert_eq!(agent, Agent::QLearning);
+                    assert_eq!(runs, Some(5));
+                    assert_eq!(stop.limits().time, Some(Duration::from_secs(45 * 60)));
+                    assert_eq!(stop.limits().episodes, None);
+                }
+                command => panic!("parsed {:?}", command),
+            }
+
+            assert!(Cli::try_parse_from(["rusty2048", "play", "--size", "1x4"]).is_err());
+            assert!(Cli::try_parse_from(["rusty2048", "train", "--reward", "nope"]).is_err());
+            assert!(Cli::try_parse_from(["rusty2048", "test", "--record", "best.txt"]).is_err());
+            assert!(Cli::try_parse_from(["rusty2048", "test", "--record", "best.qtable"]).is_err());
+            assert!(Cli::try_parse_from(["rusty2048", "test", "--record", "best.ron"]).is_ok());
+            assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+            assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+            assert!(parse_duration("2 days").is_err());
+            assert!(Cli::try_parse_from(["rusty2048"]).unwrap().command.is_none());
+        }
+
+        #[test]
+        fn rejects_flags_the_ntuple_network_ignores() {
+            for flag in [["--runs", "5"], ["--threads", "2"], ["--metrics", "log.csv"]] {
+                let cli = Cli::try_parse_from(["rusty2048", "train", "--agent", "ntuple"].into_iter().chain(flag)).unwrap();
+                let error = run(cli.command.unwrap()).unwrap_err();
+                assert!(error.to_string().contains("Q-learning"), "{}", flag[0]);
+            }
+        }
+
+        #[test]
+        fn replay_rejects_invalid_games() {
+            let mut record = play_game(&mut Random::with_seed(1), &GameConfig::with_size(2, 2), Report::Quiet);
+            record.config.spawn_weights.clear();
+            let path = temp_path("invalid.ron");
+            ModelStore::new(&path).unwrap().save(&record).unwrap();
+            let replayed = run(Command::Replay { file: path.clone(), delay: 0 });
+            fs::remove_file(&path).unwrap();
+            assert!(replayed.unwrap_err().to_string().contains("invalid game"));
+        }
+
+        #[test]
+        fn rejects_ntuple_boards_other_than_4x4() {
+            for args in [["train", "--agent", "ntuple"], ["bench", "--player", "ntuple"]] {
+                let cli = Cli::try_parse_from(["rusty2048"].into_iter().chain(args).chain(["--size", "5x5"])).unwrap();
+                let error = run(cli.command.unwrap()).unwrap_err();
+                assert!(error.to_string().contains("4x4"));
+            }
+        }
+
+        // Takes back a stop asked for in a test once dropped, even when the
+        // test fails, so the flag doesn't cut short training in later tests
+        struct ClearStop;
+
+        impl Drop for ClearStop {
+            fn drop(&mut self) {
+                ai::request_stop(false);
+            }
+        }
+
+        #[test]
+        fn resumes_checkpoints_and_saves_when_stopped() {
+            let path = temp_path("resume.bin");
+            let store = ModelStore::new(&path).unwrap();
+            let learning = LearningArgs {
+                iterations: 50,
+                ..LearningArgs::default()
+            };
+            let config = GameConfig::default();
+            let schedule = |runs| Schedule {
+                iterations: 50,
+                runs: Some(runs),
+                limits: Limits::default(),
+                threads: 1,
+                checkpoint_every: 2,
+                seed: None,
+            };
+            let mut log = MetricsLog::new(None, 100).unwrap();
+
+            train_q_learning(&store, &learning, &schedule(2), &config, true, &mut log).unwrap();
+            // Runs play their last game out, so take at least their steps
+            let first = store.load_model().unwrap().metadata;
+            assert!(first.iterations >= 100);
+            train_q_learning(&store, &learning, &schedule(1), &config, false, &mut log).unwrap();
+            let resumed = store.load_model().unwrap().metadata;
+            assert!(resumed.iterations >= first.iterations + 50);
+            assert_eq!(resumed.created, first.created);
+
+            // Checkpoints come every two runs but not after the last, and a
+            // stop asked for in the meantime ends training at the next run
+            let (mut trainer, metadata) = load_trainer(&store).unwrap();
+            let clear = ClearStop;
+            let mut checkpoints = Vec::new();
+            let (done, _) = ai::train(&mut trainer, &metadata.hyperparameters, &schedule(6), &config, |_| {}, |_, done, _| {
+                checkpoints.push(done);
+                ai::request_stop(done == 4);
+            });
+            assert_eq!((done, checkpoints), (4, vec![2, 4]));
+
+            // A stopped run still saves what it has
+            ai::request_stop(true);
+            let stopped = train_q_learning(&store, &learning, &schedule(5), &config, false, &mut log);
+            drop(clear);
+            let saved = store.load_model().unwrap().metadata;
+            fs::remove_file(&path).unwrap();
+            stopped.unwrap();
+            assert_eq!(saved.iterations, resumed.iterations);
+            assert!(saved.saved > resumed.saved);
+        }
+
+        #[test]
+        fn loads_legacy_tables_for_training() {
+            let path = temp_path("trainer-legacy.ron");
+            let empty = "((0,0,0,0),(0,0,0,0),(0,0,0,0),(0,0,0,0))";
+            let legacy = format!(
+                "{{(board:((0,2,0,0),(0,0,0,0),(0,0,0,0),(0,0,0,2)),prev_board:{},score:0,prev_score:0,merged_last:0,last_action:MergeDown):{{MergeDown:1.0}}}}",
+                empty
+            );
+            fs::write(&path, legacy).unwrap();
+            let loaded = load_trainer(&ModelStore::new(&path).unwrap());
+            fs::remove_file(&path).unwrap();
+
+            let (trainer, metadata) = loaded.unwrap();
+            assert_eq!(metadata.iterations, 0);
+            assert_eq!(trainer.export_learned_values().len(), 1);
+        }
+
+        #[test]
+        fn resumes_with_the_saved_reward() {
+            let saved = Hyperparameters {
+                reward: Reward::Score,
+                ..Hyperparameters::default()
+            };
+            assert_eq!(LearningArgs::default().hyperparameters().unwrap().reward, Reward::Shaped);
+            assert_eq!(LearningArgs::default().resumed(&saved).unwrap().reward, Reward::Score);
+            let cli = Cli::try_parse_from(["rusty2048", "train", "--reward", "monotonic"]).unwrap();
+            match cli.command {
+                Some(Command::Train { learning, .. }) => {
+                    assert_eq!(learning.resumed(&saved).unwrap().reward, Reward::Monotonic);
+                }
+                command => panic!("parsed {:?}", command),
+            }
+        }
+
+        #[test]
+        fn builds_the_exploration_policy() {
+            assert_eq!(LearningArgs::default().exploration(), Ok(Exploration::Random));
+            let cli = Cli::try_parse_from([
+                "rusty2048", "train", "--exploration", "epsilon-greedy", "--epsilon-end", "0.1", "--decay", "exponential",
+            ])
+            .unwrap();
+            match cli.command {
+                Some(Command::Train { learning, .. }) => {
+                    let epsilon = Decay {
+                        start: 1.0,
+                        end: 0.1,
+                        steps: 1_000_000,
+                        shape: Shape::Exponential,
+                    };
+                    assert_eq!(learning.exploration(), Ok(Exploration::EpsilonGreedy(epsilon)));
+                    let stuck = LearningArgs { epsilon_end: 0.0, ..learning.clone() };
+                    assert!(stuck.exploration().is_err());
+                    let unlikely = LearningArgs { epsilon: 2.0, ..learning };
+                    assert!(unlikely.exploration().is_err());
+                }
+                command => panic!("parsed {:?}", command),
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+pub mod evaluation {
+    //! Plays a batch of seeded games with any player and sums them up: score
+    //! spread, how often each milestone tile is reached, game length and
+    //! speed. Summaries print as a table and save as JSON or CSV so runs can
+    //! be compared later.
+    use std::fmt;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufWriter, Write};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::game::oxydized2048::{Game, GameConfig, GameStats};
+    use crate::player::player::{play_from, GameRecord, Player, Report};
+
+    /// Tiles whose reach rates a summary reports.
+    pub const MILESTONES: [u32; 4] = [512, 1024, 2048, 4096];
+
+    const CSV_HEADER: &str = "player,rows,cols,seed,games,mean_score,median_score,stddev_score,min_score,max_score,\
+        reached_512,reached_1024,reached_2048,reached_4096,win_rate,mean_moves,games_per_second,moves_per_second";
+
+    /// One worker per core, for spreading games across the machine.
+    pub fn available_threads() -> usize {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    }
+
+    /// Plays `games` games under `config` on `threads` workers, each playing
+    /// with its own `new_player(worker)`. Games are dealt from `seed` when
+    /// there is one, so the same seed deals the same games in the same order
+    /// however many threads play them. Also returns how long they took.
+    pub fn play_games<P: Player, F: Fn(usize) -> P + Sync>(
+        new_player: F,
+        config: &GameConfig,
+        games: u32,
+        seed: Option<u64>,
+        threads: usize,
+        report: Report,
+    ) -> (Vec<GameRecord>, Duration) {
+        let mut dealer = match seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let seeds: Vec<u64> = (0..games).map(|_| dealer.gen()).collect();
+        let next = AtomicUsize::new(0);
+        let play = |worker| {
+            let mut player = new_player(worker);
+            let mut records = Vec::new();
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(seed) = seeds.get(index) else {
+                    return records;
+                };
+                let mut game = Game::with_config_and_seed(config.clone(), *seed);
+                game.set_history_depth(0);
+                records.push((index, play_from(&mut player, &mut game, report)));
+            }
+        };
+
+        let start = Instant::now();
+        let mut records = if threads <= 1 {
+            play(0)
+        } else {
+            thread::scope(|scope| {
+                let workers: Vec<_> = (0..threads).map(|worker| scope.spawn(move || play(worker))).collect();
+                workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+            })
+        };
+        let elapsed = start.elapsed();
+        records.sort_unstable_by_key(|(index, _)| *index);
+        (records.into_iter().map(|(_, record)| record).collect(), elapsed)
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Summary {
+        pub games: usize,
+        pub mean_score: f64,
+        pub median_score: f64,
+        /// Population standard deviation.
+        pub stddev_score: f64,
+        pub min_score: u32,
+        pub max_score: u32,
+        /// Each of `MILESTONES` with the percentage of games reaching it.
+        pub reached: Vec<(u32, f64)>,
+        /// Percentage of games reaching the config's win target.
+        pub win_rate: f64,
+        pub mean_moves: f64,
+        pub games_per_second: f64,
+        pub moves_per_second: f64,
+    }
+
+    impl Summary {
+        pub fn new(stats: &[GameStats], elapsed: Duration) -> Summary {
+            let games = stats.len();
+            let count = games.max(1) as f64;
+            let mut scores: Vec<u32> = stats.iter().map(|stats| stats.score).collect();
+            scores.sort_unstable();
+
+            let mean_score = scores.iter().map(|score| *score as f64).sum::<f64>() / count;
+            let median_score = match games {
+                0 => 0.0,
+                n if n % 2 == 0 => (scores[n / 2 - 1] as f64 + scores[n / 2] as f64) / 2.0,
+                n => scores[n / 2] as f64,
+            };
+            let variance = scores
+                .iter()
+                .map(|score| (*score as f64 - mean_score).powi(2))
+                .sum::<f64>()
+                / count;
+            let percent = |n: usize| 100.0 * n as f64 / count;
+            let moves: u64 = stats.iter().map(|stats| stats.moves as u64).sum();
+            let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+
+            Summary {
+                games,
+                mean_score,
+                median_score,
+                stddev_score: variance.sqrt(),
+                min_score: scores.first().copied().unwrap_or(0),
+                max_score: scores.last().copied().unwrap_or(0),
+                reached: MILESTONES
+                    .iter()
+                    .map(|tile| (*tile, percent(stats.iter().filter(|stats| stats.max_tile >= *tile).count())))
+                    .collect(),
+                win_rate: percent(stats.iter().filter(|stats| stats.won).count()),
+                mean_moves: moves as f64 / count,
+                games_per_second: games as f64 / seconds,
+                moves_per_second: moves as f64 / seconds,
+            }
+        }
+    }
+
+    impl fmt::Display for Summary {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            writeln!(f, "{:<16}{:>12}", "Games", self.games)?;
+            writeln!(f, "{:<16}{:>12.1}", "Mean score", self.mean_score)?;
+            writeln!(f, "{:<16}{:>12.1}", "Median score", self.median_score)?;
+            writeln!(f, "{:<16}{:>12.1}", "Std deviation", self.stddev_score)?;
+            writeln!(f, "{:<16}{:>12}", "Min score", self.min_score)?;
+            writeln!(f, "{:<16}{:>12}", "Max score", self.max_score)?;
+            for (tile, percent) in self.reached.iter() {
+                writeln!(f, "{:<16}{:>11.1}%", format!("Reached {}", tile), percent)?;
+            }
+            writeln!(f, "{:<16}{:>11.1}%", "Win rate", self.win_rate)?;
+            writeln!(f, "{:<16}{:>12.1}", "Mean moves", self.mean_moves)?;
+            writeln!(f, "{:<16}{:>12.2}", "Games/s", self.games_per_second)?;
+            write!(f, "{:<16}{:>12.0}", "Moves/s", self.moves_per_second)
+        }
+    }
+
+    /// A summary with what produced it and every game behind it.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Evaluation {
+        pub player: String,
+        pub config: GameConfig,
+        pub seed: Option<u64>,
+        pub summary: Summary,
+        pub games: Vec<GameStats>,
+    }
+
+    impl Evaluation {
+        pub fn new(player: &str, config: &GameConfig, seed: Option<u64>, records: &[GameRecord], elapsed: Duration) -> Evaluation {
+            let games: Vec<GameStats> = records.iter().map(|record| record.stats.clone()).collect();
+            Evaluation {
+                player: player.to_owned(),
+                config: config.clone(),
+                seed,
+                summary: Summary::new(&games, elapsed),
+                games,
+            }
+        }
+
+        /// Writes everything as JSON to a `.json` path. A `.csv` path gets one
+        /// row with the summary, appended under the header if the file already
+        /// has rows, so one file can collect many evaluations.
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let path = path.as_ref();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("json") => {
+                    let mut file = BufWriter::new(File::create(path)?);
+                    serde_json::to_writer_pretty(&mut file, self)?;
+                    file.flush()
+                }
+                Some("csv") => {
+                    let new = !path.exists() || path.metadata()?.len() == 0;
+                    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+                    if new {
+                        writeln!(file, "{}", CSV_HEADER)?;
+                    }
+                    writeln!(file, "{}", self.csv_row())
+                }
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "reports are saved as .json or .csv",
+                )),
+            }
+        }
+
+        fn csv_row(&self) -> String {
+            let summary = &self.summary;
+            let mut fields = vec![
+                self.player.clone(),
+                self.config.rows.to_string(),
+                self.config.cols.to_string(),
+                self.seed.map(|seed| seed.to_string()).unwrap_or_default(),
+                summary.games.to_string(),
+                summary.mean_score.to_string(),
+                summary.median_score.to_string(),
+                summary.stddev_score.to_string(),
+                summary.min_score.to_string(),
+                summary.max_score.to_string(),
+            ];
+            fields.extend(summary.reached.iter().map(|(_, percent)| percent.to_string()));
+            fields.extend([
+                summary.win_rate.to_string(),
+                summary.mean_moves.to_string(),
+                summary.games_per_second.to_string(),
+                summary.moves_per_second.to_string(),
+            ]);
+            fields.join(",")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::temp_path;
+        use crate::player::player::Random;
+
+        fn stats(score: u32, max_tile: u32) -> GameStats {
+            GameStats {
+                seed: 0,
+                score,
+                max_tile,
+                moves: 10,
+                won: max_tile >= 2048,
+            }
+        }
+
+        #[test]
+        fn summarizes_scores_and_tiles() {
+            let games = [stats(100, 256), stats(300, 512), stats(500, 2048), stats(700, 4096)];
+            let summary = Summary::new(&games, Duration::from_secs(2));
+            assert_eq!(summary.mean_score, 400.0);
+            assert_eq!(summary.median_score, 400.0);
+            assert!((summary.stddev_score - 500f64.sqrt() * 10.0).abs() < 1e-9);
+            assert_eq!((summary.min_score, summary.max_score), (100, 700));
+            assert_eq!(summary.reached, vec![(512, 75.0), (1024, 50.0), (2048, 50.0), (4096, 25.0)]);
+            assert_eq!(summary.win_rate, 50.0);
+            assert_eq!(summary.games_per_second, 2.0);
+            assert_eq!(summary.moves_per_second, 20.0);
+
+            let empty = Summary::new(&[], Duration::ZERO);
+            assert_eq!((empty.games, empty.mean_score, empty.median_score), (0, 0.0, 0.0));
+        }
+
+        #[test]
+        fn seeded_games_repeat_and_save_as_csv() {
+            let config = GameConfig::default();
+            let (first, elapsed) = play_games(|_| Random::with_seed(1), &config, 3, Some(7), 1, Report::Quiet);
+            let (second, _) = play_games(|_| Random::with_seed(1), &config, 3, Some(7), 1, Report::Quiet);
+            assert_eq!(first, second);
+            let (spread, _) = play_games(|worker| Random::with_seed(worker as u64), &config, 6, Some(7), 3, Report::Quiet);
+            let seeds: Vec<u64> = spread.iter().map(|record| record.stats.seed).collect();
+            assert_eq!(seeds[..3], first.iter().map(|record| record.stats.seed).collect::<Vec<_>>()[..]);
+
+            let evaluation = Evaluation::new("random", &config, Some(7), &first, elapsed);
+            let path = temp_path("evaluation.csv");
+            evaluation.save(&path).unwrap();
+            evaluation.save(&path).unwrap();
+            let csv = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let lines: Vec<&str> = csv.lines().collect();
+            assert_eq!(lines.len(), 3);
+            assert_eq!(lines[0].split(',').count(), lines[1].split(',').count());
+            assert!(lines[1].starts_with("random,4,4,7,3,"));
+        }
+    }
+}
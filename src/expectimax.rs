@@ -0,0 +1,180 @@
+pub mod expectimax {
+    //! A search player: maximises over the player's moves and takes the
+    //! expectation over every tile the game could spawn in every empty cell.
+    use std::collections::HashMap;
+
+    use crate::game::oxydized2048::{Action, Board, Game};
+    use crate::heuristic::heuristic::Evaluation;
+    use crate::player::player::Player;
+
+    /// Chance branches less likely than this are evaluated rather than searched.
+    const MIN_PROBABILITY: f64 = 0.0001;
+
+    pub struct Expectimax {
+        depth: u32,
+        evaluation: Evaluation,
+        // Values of chance nodes. Cleared before every move since the search
+        // tree changes under it.
+        cache: HashMap<ChanceKey, f64>,
+    }
+
+    // Everything a chance node's value depends on. Besides the board and the
+    // remaining depth, evaluations may read the score and the last move, which
+    // differ between paths to the same board.
+    #[derive(PartialEq, Eq, Hash)]
+    struct ChanceKey {
+        board: Board,
+        depth: u32,
+        score: u32,
+        last_action: Action,
+        merged_last: u32,
+    }
+
+    impl ChanceKey {
+        fn new(after: &Game, depth: u32) -> ChanceKey {
+            ChanceKey {
+                board: after.board.clone(),
+                depth,
+                score: after.score,
+                last_action: after.last_action.clone(),
+                merged_last: after.merged_last,
+            }
+        }
+    }
+
+    impl Expectimax {
+        /// `depth` counts the player's moves to look ahead, with a spawn after each.
+        pub fn new(depth: u32, evaluation: Evaluation) -> Expectimax {
+            assert!(depth > 0, "search depth must be at least one move");
+            Expectimax {
+                depth,
+                evaluation,
+                cache: HashMap::new(),
+            }
+        }
+
+        pub fn best_action(&mut self, game: &Game) -> Option<Action> {
+            self.cache.clear();
+            let mut best: Option<(Action, f64)> = None;
+            for action in game.get_valid_actions() {
+                let after = match game.afterstate(&action) {
+                    Some(after) => after,
+                    None => continue,
+                };
+                let value = self.chance(&after, self.depth, 1.0);
+                match &best {
+                    Some((_, best_value)) if value <= *best_value => {}
+                    _ => best = Some((action, value)),
+                }
+            }
+            best.map(|(action, _)| action)
+        }
+
+        fn max(&mut self, game: &Game, depth: u32, probability: f64) -> f64 {
+            if depth == 0 || probability < MIN_PROBABILITY {
+                return (self.evaluation)(game);
+            }
+            let mut best: Option<f64> = None;
+            for action in game.get_valid_actions() {
+                if let Some(after) = game.afterstate(&action) {
+                    let value = self.chance(&after, depth, probability);
+                    best = Some(best.map_or(value, |best| best.max(value)));
+                }
+            }
+            // No moves left: a lost game is worth nothing
+            best.unwrap_or(0.0)
+        }
+
+        // Models one spawn per move, whatever the config's tiles_per_move.
+        fn chance(&mut self, after: &Game, depth: u32, probability: f64) -> f64 {
+            let key = ChanceKey::new(after, depth);
+            if let Some(value) = self.cache.get(&key) {
+                return *value;
+            }
+
+            let cells = after.empty_cells();
+            if cells.is_empty() {
+                return self.max(after, depth - 1, probability);
+            }
+            let weights = after.config().spawn_weights.clone();
+            let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+
+            let mut value = 0.0;
+            let mut child = after.clone();
+            for (i, j) in cells.iter() {
+                for (tile, weight) in weights.iter() {
+                    if *weight <= 0.0 {
+                        continue;
+                    }
+                    let p = weight / total / cells.len() as f64;
+                    child.board.set(*i, *j, *tile);
+                    value += p * self.max(&child, depth - 1, probability * p);
+                }
+                child.board.set(*i, *j, 0);
+            }
+
+            self.cache.insert(key, value);
+            value
+        }
+    }
+
+    impl Player for Expectimax {
+        fn choose(&mut self, game: &Game) -> Action {
+            self.best_action(game)
+                .expect("choose is only called while a move is left")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::heuristic::heuristic;
+
+        #[test]
+        fn takes_the_obvious_merge() {
+            let mut game = Game::with_seed(5);
+            game.board = vec![
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![1024, 1024, 0, 0],
+            ]
+            .into();
+            let mut player = Expectimax::new(1, heuristic::score);
+            let action = player.best_action(&game).unwrap();
+            assert!(action == Action::MergeLeft || action == Action::MergeRight);
+        }
+
+        #[test]
+        fn caches_by_score_as_well_as_board() {
+            let mut game = Game::with_seed(5);
+            game.board = vec![
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 2, 4],
+            ]
+            .into();
+            let mut richer = game.clone();
+            richer.score += 100;
+
+            let mut player = Expectimax::new(1, heuristic::score);
+            let value = player.chance(&game, 1, 1.0);
+            assert_eq!(player.chance(&richer, 1, 1.0), value + 100.0);
+        }
+
+        #[test]
+        fn has_no_move_on_a_lost_board() {
+            let mut game = Game::with_seed(5);
+            game.board = vec![
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+            ]
+            .into();
+            let mut player = Expectimax::new(2, heuristic::monotonic);
+            assert!(player.best_action(&game).is_none());
+        }
+    }
+}
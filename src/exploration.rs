@@ -0,0 +1,247 @@
+pub mod exploration {
+    //! How Q-learning picks its moves while training. Random moves learn
+    //! values without ever following them; the other policies lean more and
+    //! more on what has been learned, by a probability or temperature that
+    //! decays over training, or by a bonus for moves tried least.
+    use std::collections::HashMap;
+    use std::fmt;
+
+    use rand::distributions::WeightedIndex;
+    use rand::prelude::*;
+    use serde::{Deserialize, Serialize};
+
+    use crate::game::oxydized2048::Action;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Shape {
+        Constant,
+        Linear,
+        /// Scales the value by the same factor every step, so both ends must
+        /// be above 0.
+        Exponential,
+    }
+
+    /// A value going from `start` to `end` over the first `steps` training
+    /// steps, then staying at `end`.
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Decay {
+        pub start: f64,
+        pub end: f64,
+        pub steps: u64,
+        pub shape: Shape,
+    }
+
+    impl Decay {
+        pub fn value(&self, step: u64) -> f64 {
+            let progress = match self.steps {
+                0 => 1.0,
+                steps => (step as f64 / steps as f64).min(1.0),
+            };
+            match self.shape {
+                Shape::Constant => self.start,
+                Shape::Linear => self.start + (self.end - self.start) * progress,
+                Shape::Exponential => self.start * (self.end / self.start).powf(progress),
+            }
+        }
+    }
+
+    impl fmt::Display for Decay {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.shape {
+                Shape::Constant => write!(f, "{}", self.start),
+                Shape::Linear => write!(f, "{} to {} linearly over {} steps", self.start, self.end, self.steps),
+                Shape::Exponential => write!(f, "{} to {} exponentially over {} steps", self.start, self.end, self.steps),
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    pub enum Exploration {
+        /// Uniformly random moves.
+        #[default]
+        Random,
+        /// A random move with probability epsilon, else the best learned one.
+        EpsilonGreedy(Decay),
+        /// Moves drawn with weights `exp(value / temperature)`.
+        Softmax(Decay),
+        /// The move with the best value plus `c * sqrt(ln n / n_a)`, where `n`
+        /// counts the moves tried from the board and `n_a` those of this move.
+        /// Moves never tried from the board go first.
+        Ucb { c: f64 },
+    }
+
+    impl fmt::Display for Exploration {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Exploration::Random => write!(f, "random"),
+                Exploration::EpsilonGreedy(epsilon) => write!(f, "epsilon-greedy, epsilon {}", epsilon),
+                Exploration::Softmax(temperature) => write!(f, "softmax, temperature {}", temperature),
+                Exploration::Ucb { c } => write!(f, "UCB, c {}", c),
+            }
+        }
+    }
+
+    /// Picks training moves under an `Exploration`, counting the steps its
+    /// decay follows and, for UCB, the tries of each move from each board.
+    #[derive(Clone, Debug)]
+    pub struct Explorer {
+        exploration: Exploration,
+        step: u64,
+        // Tries of each action, by `Action as usize`, keyed like the boards
+        tries: HashMap<u64, [u32; 4]>,
+    }
+
+    impl Explorer {
+        pub fn new(exploration: Exploration) -> Explorer {
+            Explorer {
+                exploration,
+                step: 0,
+                tries: HashMap::new(),
+            }
+        }
+
+        pub fn exploration(&self) -> &Exploration {
+            &self.exploration
+        }
+
+        /// Where the decay picks up from; every `choose` moves it one step on.
+        pub fn set_step(&mut self, step: u64) {
+            self.step = step;
+        }
+
+        /// Picks one of `actions`, which must not be empty, from the board
+        /// `key` stands for. `values` are what was learned for the board, and
+        /// actions missing from it count as `initial_value`.
+        pub fn choose(
+            &mut self,
+            key: u64,
+            actions: &[Action],
+            values: Option<&HashMap<Action, f64>>,
+            initial_value: f64,
+        ) -> Action {
+            let value = |action: &Action| values.and_then(|values| values.get(action)).copied().unwrap_or(initial_value);
+            let best = || {
+                actions
+                    .iter()
+                    .max_by(|a, b| value(a).total_cmp(&value(b)))
+                    .unwrap()
+                    .clone()
+            };
+            let mut rng = thread_rng();
+            let step = self.step;
+            self.step += 1;
+
+            match &self.exploration {
+                Exploration::Random => actions.choose(&mut rng).unwrap().clone(),
+                Exploration::EpsilonGreedy(epsilon) => {
+                    if rng.gen::<f64>() < epsilon.value(step) {
+                        actions.choose(&mut rng).unwrap().clone()
+                    } else {
+                        best()
+                    }
+                }
+                Exploration::Softmax(temperature) => {
+                    let temperature = temperature.value(step).max(f64::MIN_POSITIVE);
+                    // Shifted by the best value so the weights can't overflow
+                    let top = value(&best());
+                    let weights = actions.iter().map(|action| ((value(action) - top) / temperature).exp());
+                    match WeightedIndex::new(weights) {
+                        Ok(weights) => actions[weights.sample(&mut rng)].clone(),
+                        Err(_) => best(),
+                    }
+                }
+                Exploration::Ucb { c } => {
+                    let tries = self.tries.entry(key).or_insert([0; 4]);
+                    let total = tries.iter().sum::<u32>().max(1) as f64;
+                    let score = |action: &Action| match tries[action.clone() as usize] {
+                        0 => f64::INFINITY,
+                        n => value(action) + c * (total.ln() / n as f64).sqrt(),
+                    };
+                    let action = actions
+                        .iter()
+                        .max_by(|a, b| score(a).total_cmp(&score(b)))
+                        .unwrap()
+                        .clone();
+                    tries[action.clone() as usize] += 1;
+                    action
+                }
+            }
+        }
+
+        /// Adds the tries `worker` made since it was cloned from `base`.
+        pub fn absorb(&mut self, base: &Explorer, worker: &Explorer) {
+            for (key, tries) in worker.tries.iter() {
+                let before = base.tries.get(key).copied().unwrap_or([0; 4]);
+                let counts = self.tries.entry(*key).or_insert([0; 4]);
+                for ((count, after), before) in counts.iter_mut().zip(tries).zip(before) {
+                    *count += after - before;
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const ACTIONS: [Action; 3] = [Action::MergeLeft, Action::MergeUp, Action::MergeDown];
+
+        fn values() -> HashMap<Action, f64> {
+            let mut values = HashMap::new();
+            values.insert(Action::MergeLeft, 1.0);
+            values.insert(Action::MergeUp, 5.0);
+            values
+        }
+
+        #[test]
+        fn decays_from_start_to_end() {
+            let linear = Decay {
+                start: 1.0,
+                end: 0.2,
+                steps: 100,
+                shape: Shape::Linear,
+            };
+            assert_eq!(linear.value(0), 1.0);
+            assert!((linear.value(50) - 0.6).abs() < 1e-9);
+            assert!((linear.value(1000) - 0.2).abs() < 1e-9);
+
+            let exponential = Decay { shape: Shape::Exponential, ..linear };
+            assert!((exponential.value(50) - 0.2f64.sqrt()).abs() < 1e-9);
+            assert!((exponential.value(100) - 0.2).abs() < 1e-9);
+            assert_eq!(Decay { shape: Shape::Constant, ..linear }.value(100), 1.0);
+        }
+
+        #[test]
+        fn greedy_policies_follow_the_best_value() {
+            let greedy = Decay {
+                start: 0.0,
+                end: 0.0,
+                steps: 0,
+                shape: Shape::Constant,
+            };
+            let mut explorer = Explorer::new(Exploration::EpsilonGreedy(greedy));
+            assert_eq!(explorer.choose(1, &ACTIONS, Some(&values()), 0.5), Action::MergeUp);
+            // An action never tried counts at the initial value
+            assert_eq!(explorer.choose(1, &ACTIONS, Some(&values()), 9.0), Action::MergeDown);
+
+            let cold = Decay { start: 1e-6, ..greedy };
+            let mut explorer = Explorer::new(Exploration::Softmax(cold));
+            assert_eq!(explorer.choose(1, &ACTIONS, Some(&values()), 0.5), Action::MergeUp);
+        }
+
+        #[test]
+        fn ucb_tries_every_move_before_repeating_one() {
+            let mut explorer = Explorer::new(Exploration::Ucb { c: 1.0 });
+            let mut tried: Vec<Action> = (0..3).map(|_| explorer.choose(7, &ACTIONS, Some(&values()), 0.5)).collect();
+            tried.sort_by_key(|action| action.clone() as usize);
+            assert_eq!(tried, ACTIONS.to_vec());
+            assert_eq!(explorer.choose(7, &ACTIONS, Some(&values()), 0.5), Action::MergeUp);
+
+            let base = Explorer::new(Exploration::Ucb { c: 1.0 });
+            let mut merged = base.clone();
+            merged.absorb(&base, &explorer);
+            merged.absorb(&base, &explorer);
+            assert_eq!(merged.tries[&7].iter().sum::<u32>(), 8);
+        }
+    }
+}
@@ -1,17 +1,284 @@
 pub mod oxydized2048 {
+    use std::collections::VecDeque;
+    use std::hash::Hash;
+
     use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
     use serde::{Serialize, Deserialize};
 
-    #[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+    use crate::bitboard::bitboard::{self, Bitboard};
+
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Game {
-        pub board: [[u32; 4]; 4],
-        pub prev_board: [[u32; 4]; 4],
+        pub board: Board,
+        pub prev_board: Board,
         pub score: u32,
         pub prev_score: u32,
         pub merged_last: u32,
         pub last_action: Action,
+        seed: u64,
+        rng: ChaCha8Rng,
+        #[serde(skip)]
+        history: VecDeque<Snapshot>,
+        #[serde(skip)]
+        future: Vec<Snapshot>,
+        #[serde(skip, default = "default_history_depth")]
+        history_depth: usize,
+        config: GameConfig,
+        won: bool,
+        moves: u32,
     }
-    
+
+    /// A game's tiles. 4x4 boards of ordinary tiles are kept packed, so moves
+    /// go through the bitboard tables without converting; any other board is
+    /// kept as rows. Both are saved as rows.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    #[serde(from = "Vec<Vec<u32>>", into = "Vec<Vec<u32>>")]
+    pub struct Board(Tiles);
+
+    // Only boards `Bitboard::from_rows` rejects are kept as rows, so the same
+    // tiles always make equal boards. Only `Board::from` and `Board::packed`
+    // pick the variant.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    enum Tiles {
+        Packed(Bitboard),
+        Rows(Vec<Vec<u32>>),
+    }
+
+    impl Board {
+        pub fn empty(rows: usize, cols: usize) -> Board {
+            Board::from(vec![vec![0; cols]; rows])
+        }
+
+        // A packed board holding a tile past `MAX_EXACT_TILE` can't be moved
+        // exactly by the tables any more, so it goes back to rows.
+        fn packed(bits: Bitboard) -> Board {
+            if bits.max_tile() > bitboard::MAX_EXACT_TILE {
+                Board(Tiles::Rows(bits.to_rows()))
+            } else {
+                Board(Tiles::Packed(bits))
+            }
+        }
+
+        /// The packed board, when the tables can move it.
+        pub fn bitboard(&self) -> Option<Bitboard> {
+            match &self.0 {
+                Tiles::Packed(bits) => Some(*bits),
+                Tiles::Rows(_) => None,
+            }
+        }
+
+        pub fn to_rows(&self) -> Vec<Vec<u32>> {
+            match &self.0 {
+                Tiles::Packed(bits) => bits.to_rows(),
+                Tiles::Rows(rows) => rows.clone(),
+            }
+        }
+
+        pub fn rows(&self) -> usize {
+            match &self.0 {
+                Tiles::Packed(_) => 4,
+                Tiles::Rows(rows) => rows.len(),
+            }
+        }
+
+        pub fn cols(&self) -> usize {
+            match &self.0 {
+                Tiles::Packed(_) => 4,
+                Tiles::Rows(rows) => rows[0].len(),
+            }
+        }
+
+        pub fn get(&self, row: usize, col: usize) -> u32 {
+            match &self.0 {
+                Tiles::Packed(bits) => bits.tile(row * 4 + col),
+                Tiles::Rows(rows) => rows[row][col],
+            }
+        }
+
+        pub fn set(&mut self, row: usize, col: usize, value: u32) {
+            match &mut self.0 {
+                Tiles::Packed(bits) => match bitboard::exponent_of(value) {
+                    Some(exponent) => *bits = bits.with_exponent(row * 4 + col, exponent),
+                    None => {
+                        let mut rows = bits.to_rows();
+                        rows[row][col] = value;
+                        *self = Board(Tiles::Rows(rows));
+                    }
+                },
+                Tiles::Rows(rows) => {
+                    rows[row][col] = value;
+                    *self = Board::from(std::mem::take(rows));
+                }
+            }
+        }
+
+        pub fn max_tile(&self) -> u32 {
+            match &self.0 {
+                Tiles::Packed(bits) => bits.max_tile(),
+                Tiles::Rows(rows) => rows.iter().flatten().copied().max().unwrap_or(0),
+            }
+        }
+
+        /// Positions `(row, col)` of every empty cell, in reading order.
+        pub fn empty_cells(&self) -> Vec<(usize, usize)> {
+            match &self.0 {
+                Tiles::Packed(bits) => bits.empty_positions().map(|cell| (cell / 4, cell % 4)).collect(),
+                Tiles::Rows(rows) => rows
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, row)| {
+                        row.iter()
+                            .enumerate()
+                            .filter(|(_, col)| **col == 0_u32)
+                            .map(move |(j, _)| (i, j))
+                    })
+                    .collect(),
+            }
+        }
+
+        // Slides every tile for `action`, returning the value of the merged tiles.
+        fn shift(&mut self, action: &Action) -> u32 {
+            match &mut self.0 {
+                Tiles::Packed(bits) => {
+                    let (moved, merged) = bits.shift(action);
+                    *self = Board::packed(moved);
+                    merged
+                }
+                Tiles::Rows(rows) => {
+                    let merged = shift_rows(rows, action);
+                    *self = Board::from(std::mem::take(rows));
+                    merged
+                }
+            }
+        }
+    }
+
+    impl From<Vec<Vec<u32>>> for Board {
+        fn from(rows: Vec<Vec<u32>>) -> Board {
+            match Bitboard::from_rows(&rows) {
+                Some(bits) => Board(Tiles::Packed(bits)),
+                None => Board(Tiles::Rows(rows)),
+            }
+        }
+    }
+
+    impl From<Board> for Vec<Vec<u32>> {
+        fn from(board: Board) -> Vec<Vec<u32>> {
+            match board.0 {
+                Tiles::Packed(bits) => bits.to_rows(),
+                Tiles::Rows(rows) => rows,
+            }
+        }
+    }
+
+    impl PartialEq<Vec<Vec<u32>>> for Board {
+        fn eq(&self, rows: &Vec<Vec<u32>>) -> bool {
+            match &self.0 {
+                Tiles::Packed(bits) => Bitboard::from_rows(rows) == Some(*bits),
+                Tiles::Rows(own) => own == rows,
+            }
+        }
+    }
+
+    /// A summary of a game, typically read once `action` returns `GameState::Gameover`.
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct GameStats {
+        pub seed: u64,
+        pub score: u32,
+        pub max_tile: u32,
+        pub moves: u32,
+        pub won: bool,
+    }
+
+    /// The rules a game is played under. `GameConfig::default()` is the
+    /// canonical 4x4 game; anything else is a house variant.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct GameConfig {
+        pub rows: usize,
+        pub cols: usize,
+        /// Tiles placed on the empty board before the first move.
+        pub initial_tiles: usize,
+        /// Values a spawned tile can take, each with its relative weight.
+        pub spawn_weights: Vec<(u32, f64)>,
+        /// Tiles spawned after every move that changes the board.
+        pub tiles_per_move: usize,
+        /// The tile that counts as winning the game.
+        pub win_target: u32,
+    }
+
+    impl Default for GameConfig {
+        fn default() -> GameConfig {
+            GameConfig {
+                rows: 4,
+                cols: 4,
+                initial_tiles: 2,
+                spawn_weights: vec![(2, 0.9), (4, 0.1)],
+                tiles_per_move: 1,
+                win_target: 2048,
+            }
+        }
+    }
+
+    impl GameConfig {
+        pub fn with_size(rows: usize, cols: usize) -> GameConfig {
+            GameConfig {
+                rows,
+                cols,
+                ..GameConfig::default()
+            }
+        }
+
+        pub fn validate(&self) -> Result<(), String> {
+            if self.rows == 0 || self.cols == 0 {
+                return Err("board must have at least one row and column".to_owned());
+            }
+            if self.initial_tiles > self.rows * self.cols {
+                return Err(format!(
+                    "{} initial tiles do not fit on a {}x{} board",
+                    self.initial_tiles, self.rows, self.cols
+                ));
+            }
+            if self.spawn_weights.is_empty() {
+                return Err("at least one spawn value is required".to_owned());
+            }
+            for (value, weight) in self.spawn_weights.iter() {
+                if *value == 0 {
+                    return Err("spawn values must be non-zero".to_owned());
+                }
+                if !weight.is_finite() || *weight < 0.0 {
+                    return Err(format!("spawn weight for {} must be a non-negative number", value));
+                }
+            }
+            if self.spawn_weights.iter().map(|(_, weight)| weight).sum::<f64>() <= 0.0 {
+                return Err("spawn weights must not all be zero".to_owned());
+            }
+            Ok(())
+        }
+    }
+
+    /// How many moves `undo` can step back unless `set_history_depth` says otherwise.
+    pub const DEFAULT_HISTORY_DEPTH: usize = 1;
+
+    fn default_history_depth() -> usize {
+        DEFAULT_HISTORY_DEPTH
+    }
+
+    /// Everything `undo`/`redo` needs to put a game back exactly as it was,
+    /// including the RNG so the same tiles spawn again.
+    #[derive(Clone)]
+    struct Snapshot {
+        board: Board,
+        prev_board: Board,
+        score: u32,
+        prev_score: u32,
+        merged_last: u32,
+        last_action: Action,
+        rng: ChaCha8Rng,
+        won: bool,
+        moves: u32,
+    }
+
     #[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
     pub enum Action {
         MergeLeft,
@@ -25,48 +292,187 @@ pub mod oxydized2048 {
         Gameover,
         InvalidMove,
         Ok,
+        /// The move created the config's win target for the first time. The
+        /// game carries on as normal if the caller keeps playing.
+        Won,
     }
     
     impl Game {
         pub fn new() -> Game {
+            Game::with_config(GameConfig::default())
+        }
+
+        pub fn with_seed(seed: u64) -> Game {
+            Game::with_config_and_seed(GameConfig::default(), seed)
+        }
+
+        pub fn with_size(rows: usize, cols: usize) -> Game {
+            Game::with_config(GameConfig::with_size(rows, cols))
+        }
+
+        pub fn with_size_and_seed(rows: usize, cols: usize, seed: u64) -> Game {
+            Game::with_config_and_seed(GameConfig::with_size(rows, cols), seed)
+        }
+
+        pub fn with_config(config: GameConfig) -> Game {
+            Game::with_config_and_seed(config, thread_rng().gen())
+        }
+
+        /// A config and seed plus the sequence of actions played fully determines a game.
+        pub fn with_config_and_seed(config: GameConfig, seed: u64) -> Game {
+            if let Err(e) = config.validate() {
+                panic!("invalid game config: {}", e);
+            }
+            let (rows, cols) = (config.rows, config.cols);
             let mut game = Game {
-                board: [[0; 4]; 4],
-                prev_board: [[0; 4]; 4],
+                board: Board::empty(rows, cols),
+                prev_board: Board::empty(rows, cols),
                 score: 0,
                 prev_score: 0,
                 merged_last: 0,
                 last_action: Action::MergeDown,
+                seed,
+                rng: ChaCha8Rng::seed_from_u64(seed),
+                history: VecDeque::new(),
+                future: Vec::new(),
+                history_depth: DEFAULT_HISTORY_DEPTH,
+                config,
+                won: false,
+                moves: 0,
             };
-            game.place_next();
+            for _ in 0..game.config.initial_tiles {
+                game.place_next();
+            }
             game
         }
 
-        pub fn undo(&mut self) {
-            self.board = self.prev_board;
+        /// Steps back one move, returning false when there is nothing to undo.
+        pub fn undo(&mut self) -> bool {
+            match self.history.pop_back() {
+                Some(snapshot) => {
+                    self.future.push(self.snapshot());
+                    self.restore(snapshot);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        /// Re-applies the last undone move, returning false when there is nothing to redo.
+        pub fn redo(&mut self) -> bool {
+            match self.future.pop() {
+                Some(snapshot) => {
+                    self.history.push_back(self.snapshot());
+                    self.restore(snapshot);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        pub fn set_history_depth(&mut self, depth: usize) {
+            self.history_depth = depth;
+            while self.history.len() > depth {
+                self.history.pop_front();
+            }
+        }
+
+        fn snapshot(&self) -> Snapshot {
+            Snapshot {
+                board: self.board.clone(),
+                prev_board: self.prev_board.clone(),
+                score: self.score,
+                prev_score: self.prev_score,
+                merged_last: self.merged_last,
+                last_action: self.last_action.clone(),
+                rng: self.rng.clone(),
+                won: self.won,
+                moves: self.moves,
+            }
+        }
+
+        fn restore(&mut self, snapshot: Snapshot) {
+            self.board = snapshot.board;
+            self.prev_board = snapshot.prev_board;
+            self.score = snapshot.score;
+            self.prev_score = snapshot.prev_score;
+            self.merged_last = snapshot.merged_last;
+            self.last_action = snapshot.last_action;
+            self.rng = snapshot.rng;
+            self.won = snapshot.won;
+            self.moves = snapshot.moves;
+        }
+
+        pub fn seed(&self) -> u64 {
+            self.seed
+        }
+
+        pub fn config(&self) -> &GameConfig {
+            &self.config
+        }
+
+        /// Whether the win target has been reached at any point in this game.
+        pub fn has_won(&self) -> bool {
+            self.won
+        }
+
+        pub fn max_tile(&self) -> u32 {
+            self.board.max_tile()
+        }
+
+        /// Moves that changed the board; invalid moves are not counted.
+        pub fn moves(&self) -> u32 {
+            self.moves
+        }
+
+        pub fn stats(&self) -> GameStats {
+            GameStats {
+                seed: self.seed,
+                score: self.score,
+                max_tile: self.max_tile(),
+                moves: self.moves,
+                won: self.won,
+            }
+        }
+
+        pub fn rows(&self) -> usize {
+            self.board.rows()
+        }
+
+        pub fn cols(&self) -> usize {
+            self.board.cols()
         }
 
         pub fn action(&mut self, action: &Action) -> GameState {
-            let original = self.board;
-            self.prev_score = self.calc_score();
+            let before = if self.history_depth > 0 { Some(self.snapshot()) } else { None };
+            let original = self.board.clone();
+            self.prev_score = self.score;
             self.last_action = action.clone();
-            match action {
-                Action::MergeLeft => self.merge_left(),
-                Action::MergeRight => self.merge_right(),
-                Action::MergeUp => self.merge_up(),
-                Action::MergeDown => self.merge_down(),
-            }
+            self.shift(action);
             if self.board == original {
                 if self.is_gameover() {
-                    self.reset();
                     GameState::Gameover
                 } else {
                     GameState::InvalidMove
                 }
             } else {
-                self.place_next();
-                self.score = self.calc_score();
-                if self.is_gameover() {
-                    self.reset();
+                if let Some(before) = before {
+                    if self.history.len() == self.history_depth {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back(before);
+                }
+                self.future.clear();
+                self.prev_board = original;
+                self.moves += 1;
+                for _ in 0..self.config.tiles_per_move {
+                    self.place_next();
+                }
+                self.score += self.merged_last;
+                if !self.won && self.max_tile() >= self.config.win_target {
+                    self.won = true;
+                    GameState::Won
+                } else if self.is_gameover() {
                     GameState::Gameover
                 } else {
                     GameState::Ok
@@ -74,125 +480,97 @@ pub mod oxydized2048 {
             }
         }
         
-        pub fn reset(&mut self){
-            *self = Game::new();
-        }
-        
-        fn place_next(&mut self){
-            let mut zeros = 0;
-            let mut picked = false;
-            let mut rng = thread_rng();
-    
-            for row in self.board.iter(){
-                for col in row.iter(){
-                    if *col == 0_u32 {
-                        zeros += 1;
-                        break;
-                    }
-                }
-                if zeros > 0 {
-                    break;
-                }
-            }
-    
-            while !picked && zeros > 0{
-                for row in self.board.iter_mut() {
-                    for col in row.iter_mut() {
-                        if rng.gen_range(0..16) == 0 && *col == 0_u32 && !picked{
-                            if rng.gen_range(0..10) == 0 {
-                                *col += 4;
-                            }
-                            else {
-                                *col += 2;
-                            }
-                            picked = true;
-                        }
-                    }
-                }
+        /// The position right after sliding the tiles for `action`, before any
+        /// tile spawns, or None if the move would not change the board. The
+        /// returned game has no undo history, so it is cheap for search to copy.
+        pub fn afterstate(&self, action: &Action) -> Option<Game> {
+            let mut after = self.without_history();
+            after.prev_score = after.score;
+            after.last_action = action.clone();
+            after.shift(action);
+            if after.board == self.board {
+                return None;
             }
+            after.prev_board = self.board.clone();
+            after.moves += 1;
+            after.score += after.merged_last;
+            Some(after)
         }
-    
-        fn merge_left(&mut self){
-            self.merged_last = 0;
-            for row in self.board.iter_mut(){
-                let mut non_zeros = 0;
-                for col in row.iter(){
-                    if *col != 0{
-                        non_zeros += 1;
-                    }
-                }
-        
-                if non_zeros > 0 && non_zeros < row.len()-1{
-                    for j in 0..non_zeros{
-                        let mut k = 1;
-                        while row[j] == 0 && j+k < row.len(){
-                            if row[j+k] > 0{
-                                row[j] = row[j+k];
-                                row[j+k] = 0;
-                            }
-                            k += 1
-                    }
-                }
-        
-                }
-        
-                for j in 0..row.len()-1{
-                    let (cur, next) = (row[j], row[j+1]);
-                    if cur == next && cur != 0 && next != 0{
-                        row[j] = cur + next;
-                        self.merged_last += cur*2;
-                        row[j+1] = 0;
-                    }
-                    else if cur == 0 && next != 0{
-                        row[j] = next;
-                        row[j+1] = 0;
-        
-                    }
-                }
+
+        /// A copy of this game, without undo history, whose future spawns are
+        /// drawn from `seed` instead of this game's RNG. For simulations that
+        /// must not share the real game's upcoming tiles.
+        pub fn fork(&self, seed: u64) -> Game {
+            let mut fork = self.without_history();
+            fork.rng = ChaCha8Rng::seed_from_u64(seed);
+            fork
+        }
+
+        fn without_history(&self) -> Game {
+            Game {
+                board: self.board.clone(),
+                prev_board: self.prev_board.clone(),
+                score: self.score,
+                prev_score: self.prev_score,
+                merged_last: self.merged_last,
+                last_action: self.last_action.clone(),
+                seed: self.seed,
+                rng: self.rng.clone(),
+                history: VecDeque::new(),
+                future: Vec::new(),
+                history_depth: 0,
+                config: self.config.clone(),
+                won: self.won,
+                moves: self.moves,
             }
         }
-        
-        fn merge_right(&mut self){
-            self.reverse();
-            self.merge_left();
-            self.reverse();
-        }
-        fn merge_up(&mut self){
-            self.transpose();
-            self.merge_left();
-            self.transpose();
-        
+
+        /// Positions `(row, col)` of every empty cell, in reading order.
+        pub fn empty_cells(&self) -> Vec<(usize, usize)> {
+            self.board.empty_cells()
         }
-        fn merge_down(&mut self){
-            self.transpose();
-            self.merge_right();
-            self.transpose();
+
+        /// Starts a new game whose seed is drawn from this game's RNG, so a
+        /// whole session of resets replays from the first seed. `action` never
+        /// does this itself; a finished game stays on its final board.
+        pub fn reset(&mut self){
+            let seed = self.rng.gen();
+            let depth = self.history_depth;
+            *self = Game::with_config_and_seed(self.config.clone(), seed);
+            self.history_depth = depth;
         }
         
-        fn reverse(&mut self){
-            for row in self.board.iter_mut(){
-                row.reverse();
+        /// Spawns a tile in an empty cell chosen uniformly at random, its value
+        /// drawn from the config's spawn weights.
+        fn place_next(&mut self){
+            let empty = self.empty_cells();
+            if let Some(&(i, j)) = empty.choose(&mut self.rng) {
+                let value = self.spawn_value();
+                self.board.set(i, j, value);
             }
         }
-        
-        fn transpose(&mut self){
-            let board = self.board;
-            let mut new_board = [[0; 4]; 4];
-            // outer for loop to traverse rows
-        	for i in 0..new_board.len()
-            {
-                // inner for loop to traverse column
-                for j in 0..new_board[0].len()
-                {
-                    // insert arr[row][col] to transpose[col][row]
-                    new_board[j][i] = board[i][j];
+
+        fn spawn_value(&mut self) -> u32 {
+            let weights = &self.config.spawn_weights;
+            let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+            let mut roll = self.rng.gen::<f64>() * total;
+            for (value, weight) in weights.iter() {
+                if roll < *weight {
+                    return *value;
                 }
+                roll -= weight;
             }
-            self.board = new_board;
+            // Only reachable through floating point rounding on the last weight
+            weights.iter().rev().find(|(_, weight)| *weight > 0.0).unwrap().0
+        }
+    
+        // Slides the tiles without spawning, keeping what merged for the score.
+        fn shift(&mut self, action: &Action) {
+            self.merged_last = self.board.shift(action);
         }
         
         pub fn display(&self){
-            let board = self.board;
+            let board = self.board.to_rows();
             let mut max: u32 = 0;
             for row in board.iter(){
                 for col in row.iter(){
@@ -202,7 +580,6 @@ pub mod oxydized2048 {
         
                 }
             }
-            let board = board;
             for row in board.iter(){
                 for col in row.iter(){
                     if *col == 0_u32{
@@ -218,185 +595,574 @@ pub mod oxydized2048 {
             println!();
         }
         
-        pub fn calc_score(&self) -> u32 { 
-            let mut score: u32 = 0;
-            let mut max: u32 = 0;
-            let mut maxes: Vec<(usize, usize)> = Vec::new();
-            let mut second_maxes: Vec<(usize, usize)> = Vec::new();
-            for (row_n, row) in self.board.iter().enumerate(){
-                for (col_n, col) in row.iter().enumerate(){
-                    if *col > max {
-                        maxes.push((row_n, col_n));
-                        
-                        max = *col;
-                    }
-                    score += fast_math::log2(*col as f32) as u32;
-                    
-                }
+        pub fn get_score(&self) -> u32 {
+            self.score
+        }
+        
+        pub fn remaining_empty(&self) -> usize {
+            match &self.board.0 {
+                Tiles::Packed(bits) => bits.empty_cells() as usize,
+                Tiles::Rows(rows) => rows.iter().flatten().filter(|col| **col == 0_u32).count(),
             }
-            for (row_n, row) in self.board.iter().enumerate(){
-                for (col_n, col) in row.iter().enumerate(){
-                    if *col == max/2{
-                        second_maxes.push((row_n, col_n));
-                    }
-                }
+        }
+        
+        pub fn is_gameover(&self) -> bool{
+            match &self.board.0 {
+                Tiles::Packed(bits) => bits.is_gameover(),
+                Tiles::Rows(_) => self.remaining_empty() == 0 && self.get_valid_actions().is_empty(),
             }
+        }
+        
+        pub fn get_valid_actions(&self) -> Vec<Action> {
+            let rows = match &self.board.0 {
+                Tiles::Packed(bits) => return bits.valid_actions(),
+                Tiles::Rows(rows) => rows,
+            };
+            [Action::MergeUp, Action::MergeDown, Action::MergeRight, Action::MergeLeft]
+                .into_iter()
+                .filter(|action| {
+                    let mut moved = rows.clone();
+                    shift_rows(&mut moved, action);
+                    moved != *rows
+                })
+                .collect()
+        }
+    
+    }
 
-            let mut applied = false;
-            let mut x_prev: usize = 0;
-            let mut y_prev: usize = 0;
-            for (i, (x, y)) in maxes.iter().enumerate(){
-                if i > 0 {
-                    if x > &0 {
-                        if x - 1 == x_prev || x + 1 == x_prev && *y == y_prev {
-                            score += max*10;
-
-                        }
-                    }
-                    if y > &0 {
-                        if y - 1 == y_prev || y + 1 == y_prev && *x == x_prev {
-                            score += max*10;
-                        }
-                    }
-                }
-
+    // Slides a board kept as rows for `action`, returning the value of the
+    // merged tiles.
+    fn shift_rows(rows: &mut Vec<Vec<u32>>, action: &Action) -> u32 {
+        match action {
+            Action::MergeLeft => merge_left(rows),
+            Action::MergeRight => merge_right(rows),
+            Action::MergeUp => merge_up(rows),
+            Action::MergeDown => merge_down(rows),
+        }
+    }
 
-                if *x == 3 && !applied{
-                    //max *= 10;
-                    if *y == 3 && !applied {
-                        //println!("max: {}", max);
-                        if max >= 128 {
-                            max *= 10;
-                            applied = true;
-                        
-                        }
-                    }
-                }
-                x_prev = *x;
-                y_prev = *y;
+    fn merge_left(board: &mut [Vec<u32>]) -> u32 {
+        board.iter_mut().map(|row| slide_line(row)).sum()
+    }
+    
+    fn merge_right(board: &mut [Vec<u32>]) -> u32 {
+        reverse(board);
+        let merged = merge_left(board);
+        reverse(board);
+        merged
+    }
+    fn merge_up(board: &mut Vec<Vec<u32>>) -> u32 {
+        transpose(board);
+        let merged = merge_left(board);
+        transpose(board);
+        merged
+    }
+    fn merge_down(board: &mut Vec<Vec<u32>>) -> u32 {
+        transpose(board);
+        let merged = merge_right(board);
+        transpose(board);
+        merged
+    }
+    
+    fn reverse(board: &mut [Vec<u32>]){
+        for row in board.iter_mut(){
+            row.reverse();
+        }
+    }
+    
+    fn transpose(board: &mut Vec<Vec<u32>>){
+        let mut new_board = vec![vec![0; board.len()]; board[0].len()];
+        // outer for loop to traverse rows
+    	for i in 0..board.len()
+        {
+            // inner for loop to traverse column
+            for j in 0..board[0].len()
+            {
+                // insert arr[row][col] to transpose[col][row]
+                new_board[j][i] = board[i][j];
             }
+        }
+        *board = new_board;
+    }
 
-            if maxes.len() == 1 && second_maxes.len() > 1{
-                for (i, (x, y)) in second_maxes.iter().enumerate(){
-                    if i > 0 {
-                        if x > &0 {
-                            if x - 1 == x_prev || x + 1 == x_prev && *y == y_prev {
-                                score += (max/2)*10;
-
-                            }
-                        }
-                        if y > &0 {
-                            if y - 1 == y_prev || y + 1 == y_prev && *x == x_prev {
-                                score += (max/2)*10;
-                            }
-                        }
-                    }
-                    x_prev = *x;
-                    y_prev = *y;
-                }
-
+    /// Slides a line toward index 0 the way a left move does: tiles compact
+    /// fully, and each tile takes part in at most one merge per move. Returns
+    /// the value of the tiles created by merging.
+    fn slide_line(line: &mut [u32]) -> u32 {
+        let mut merged = 0;
+        let mut next = 0;
+        let mut can_merge = false;
+        for i in 0..line.len() {
+            let value = line[i];
+            if value == 0 {
+                continue;
             }
-            /* Additional feature I didn't end up using
-            let row4 = self.board[3].iter().sum::<u32>();
-            let row3 = self.board[2].iter().sum::<u32>();
-            let row2 = self.board[1].iter().sum::<u32>();
-            let row1 = self.board[0].iter().sum::<u32>();
-            if row4 > row3+row2+row1 {
-                score += row4*10;
-                if row3 > row2+row1 {
-                    score += row3*10;
-                    if row2 > row1 {
-                        score += row2*10;
-                    }
-                }
-            }*/
-            if self.last_action == Action::MergeDown || self.last_action == Action::MergeRight {
-                (max*10) + score*2 + self.merged_last
+            line[i] = 0;
+            if can_merge && line[next - 1] == value {
+                line[next - 1] += value;
+                merged += line[next - 1];
+                can_merge = false;
             } else {
-                (max*10) + score + self.merged_last
+                line[next] = value;
+                next += 1;
+                can_merge = true;
             }
         }
-        
-        pub fn get_score(&self) -> u32 {
-            self.score
+        merged
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // (line before a move toward index 0, line after, score gained)
+        const LINES: &[([u32; 4], [u32; 4], u32)] = &[
+            ([0, 0, 0, 0], [0, 0, 0, 0], 0),
+            ([2, 0, 0, 0], [2, 0, 0, 0], 0),
+            ([0, 0, 0, 2], [2, 0, 0, 0], 0),
+            ([0, 2, 0, 4], [2, 4, 0, 0], 0),
+            ([2, 4, 8, 16], [2, 4, 8, 16], 0),
+            ([2, 2, 0, 0], [4, 0, 0, 0], 4),
+            ([2, 0, 0, 2], [4, 0, 0, 0], 4),
+            ([0, 4, 0, 4], [8, 0, 0, 0], 8),
+            ([2, 0, 2, 4], [4, 4, 0, 0], 4),
+            ([0, 2, 2, 2], [4, 2, 0, 0], 4),
+            ([2, 2, 2, 0], [4, 2, 0, 0], 4),
+            ([2, 2, 2, 2], [4, 4, 0, 0], 8),
+            ([4, 2, 2, 0], [4, 4, 0, 0], 4),
+            ([2, 2, 4, 8], [4, 4, 8, 0], 4),
+            ([4, 4, 8, 8], [8, 16, 0, 0], 24),
+            ([8, 8, 8, 0], [16, 8, 0, 0], 16),
+            ([16, 16, 16, 16], [32, 32, 0, 0], 64),
+            ([1024, 1024, 0, 2], [2048, 2, 0, 0], 2048),
+        ];
+
+        fn game_with(board: Vec<Vec<u32>>) -> Game {
+            let mut game = Game::with_seed(0);
+            game.board = board.into();
+            game
         }
-        
-        pub fn remaining_empty(&self) -> u8 {
-            let mut zeros = 0;
-            for row in self.board.iter(){
-                for col in row.iter(){
-                    if *col == 0_u32 {
-                        zeros += 1;
-                    }
-        
+
+        fn reversed(line: &[u32; 4]) -> Vec<u32> {
+            line.iter().rev().copied().collect()
+        }
+
+        fn transposed(board: Vec<Vec<u32>>) -> Vec<Vec<u32>> {
+            (0..board[0].len())
+                .map(|j| board.iter().map(|row| row[j]).collect())
+                .collect()
+        }
+
+        // Runs every line in LINES as one row (or column) of a 4x4 board, with
+        // the other three lines rotated so each row sees a different neighbour.
+        fn check(direction: Action, orient: fn(&[u32; 4]) -> Vec<u32>, columns: bool) {
+            for i in 0..LINES.len() {
+                let rows: Vec<usize> = (0..4).map(|r| (i + r) % LINES.len()).collect();
+                let mut before: Vec<Vec<u32>> = rows.iter().map(|&r| orient(&LINES[r].0)).collect();
+                let mut after: Vec<Vec<u32>> = rows.iter().map(|&r| orient(&LINES[r].1)).collect();
+                let score: u32 = rows.iter().map(|&r| LINES[r].2).sum();
+                if columns {
+                    before = transposed(before);
+                    after = transposed(after);
                 }
+
+                let mut rows = before.clone();
+                let merged = shift_rows(&mut rows, &direction);
+                assert_eq!(rows, after, "{:?} on {:?}", direction, before);
+                assert_eq!(merged, score, "{:?} on {:?}", direction, before);
+
+                let mut game = game_with(before.clone());
+                game.shift(&direction);
+                assert_eq!(game.board, after, "{:?} on {:?}", direction, before);
+                assert_eq!(game.merged_last, score, "{:?} on {:?}", direction, before);
             }
-            zeros
         }
-        
-        pub fn is_gameover(&self) -> bool{
-            let mut new = self.clone();
-            for row in self.board.iter(){
-                for col in row.iter(){
-                    if *col == 0_u32 {
-                        return false;
+
+        #[test]
+        fn merge_left_follows_rules() {
+            check(Action::MergeLeft, |line| line.to_vec(), false);
+        }
+
+        #[test]
+        fn merge_right_follows_rules() {
+            check(Action::MergeRight, reversed, false);
+        }
+
+        #[test]
+        fn merge_up_follows_rules() {
+            check(Action::MergeUp, |line| line.to_vec(), true);
+        }
+
+        #[test]
+        fn merge_down_follows_rules() {
+            check(Action::MergeDown, reversed, true);
+        }
+
+        #[test]
+        fn merges_rectangular_boards() {
+            let mut game = game_with(vec![
+                vec![2, 2, 2, 2, 2],
+                vec![0, 4, 4, 0, 8],
+                vec![8, 0, 0, 0, 8],
+            ]);
+            game.shift(&Action::MergeLeft);
+            assert_eq!(
+                game.board,
+                vec![vec![4, 4, 2, 0, 0], vec![8, 8, 0, 0, 0], vec![16, 0, 0, 0, 0]]
+            );
+            assert_eq!(game.merged_last, 4 + 4 + 8 + 16);
+
+            game.shift(&Action::MergeUp);
+            assert_eq!(
+                game.board,
+                vec![vec![4, 4, 2, 0, 0], vec![8, 8, 0, 0, 0], vec![16, 0, 0, 0, 0]]
+            );
+            game.shift(&Action::MergeDown);
+            assert_eq!(
+                game.board,
+                vec![vec![4, 0, 0, 0, 0], vec![8, 4, 0, 0, 0], vec![16, 8, 2, 0, 0]]
+            );
+        }
+
+        #[test]
+        fn action_scores_merges_and_spawns_one_tile() {
+            let mut game = game_with(vec![
+                vec![2, 2, 4, 4],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+            ]);
+            assert_eq!(game.action(&Action::MergeLeft), GameState::Ok);
+            assert_eq!(game.board.to_rows()[0][..2], [4, 8]);
+            assert_eq!(game.get_score(), 12);
+            assert_eq!(game.remaining_empty(), 13);
+        }
+
+        #[test]
+        fn spawns_uniformly_over_empty_cells() {
+            let trials = 60_000;
+            let mut game = Game::with_seed(2048);
+            let mut counts = vec![0_u32; 16];
+            let mut fours = 0;
+            for _ in 0..trials {
+                game.board = Board::empty(4, 4);
+                game.board.set(1, 2, 8);
+                game.place_next();
+                for (cell, value) in game.board.to_rows().iter().flatten().enumerate() {
+                    match *value {
+                        2 => counts[cell] += 1,
+                        4 => {
+                            counts[cell] += 1;
+                            fours += 1;
+                        }
+                        _ => {}
                     }
-        
                 }
             }
-            new.merge_up();
-            if self.board != new.board{
-                return false;
+
+            assert_eq!(counts[6], 0, "spawned on an occupied cell");
+            assert_eq!(counts.iter().sum::<u32>(), trials);
+
+            // Chi-squared over the 15 empty cells; 36.12 is the p = 0.001 cutoff at 14 dof.
+            let expected = trials as f64 / 15.0;
+            let chi_squared: f64 = counts
+                .iter()
+                .enumerate()
+                .filter(|(cell, _)| *cell != 6)
+                .map(|(_, count)| (*count as f64 - expected).powi(2) / expected)
+                .sum();
+            assert!(chi_squared < 36.12, "chi-squared {} over {:?}", chi_squared, counts);
+
+            // Five standard deviations either side of the expected share of 4s.
+            let p = 0.1;
+            let tolerance = 5.0 * (trials as f64 * p * (1.0 - p)).sqrt();
+            assert!((fours as f64 - trials as f64 * p).abs() < tolerance, "{} fours", fours);
+        }
+
+        #[test]
+        fn spawns_in_the_last_empty_cell() {
+            let config = GameConfig {
+                spawn_weights: vec![(4, 1.0)],
+                ..GameConfig::default()
+            };
+            let mut game = Game::with_config_and_seed(config, 7);
+            game.board = vec![vec![2; 4]; 4].into();
+            game.board.set(3, 0, 0);
+            game.place_next();
+            assert_eq!(game.board.get(3, 0), 4);
+
+            game.place_next();
+            assert_eq!(game.remaining_empty(), 0);
+        }
+
+        #[test]
+        fn new_games_follow_the_config() {
+            let game = Game::with_seed(1);
+            assert_eq!(game.remaining_empty(), 14);
+
+            let config = GameConfig {
+                rows: 3,
+                cols: 5,
+                initial_tiles: 4,
+                spawn_weights: vec![(3, 1.0), (6, 0.0)],
+                tiles_per_move: 2,
+                win_target: 96,
+            };
+            let mut game = Game::with_config_and_seed(config.clone(), 1);
+            assert_eq!((game.rows(), game.cols()), (3, 5));
+            assert_eq!(game.remaining_empty(), 11);
+            assert!(game.board.to_rows().iter().flatten().all(|value| *value == 0 || *value == 3));
+
+            game.board = Board::empty(3, 5);
+            game.board.set(0, 0, 3);
+            assert_eq!(game.action(&Action::MergeRight), GameState::Ok);
+            assert_eq!(game.remaining_empty(), 12);
+
+            game.reset();
+            assert_eq!(game.config(), &config);
+        }
+
+        #[test]
+        fn rejects_invalid_configs() {
+            let invalid = [
+                GameConfig { initial_tiles: 17, ..GameConfig::default() },
+                GameConfig { spawn_weights: vec![(2, 0.0), (4, 0.0)], ..GameConfig::default() },
+                GameConfig { spawn_weights: vec![(0, 1.0)], ..GameConfig::default() },
+                GameConfig { rows: 0, ..GameConfig::default() },
+            ];
+            for config in invalid.iter() {
+                assert!(config.validate().is_err(), "{:?}", config);
             }
-        
-            new.board = self.board;
-            new.merge_down();
-            if self.board != new.board{
-                return false;
+
+            assert!(GameConfig::with_size(5, 3).validate().is_ok());
+        }
+
+        // Plays the first valid move and returns it.
+        fn play_any(game: &mut Game) -> Action {
+            let action = game.get_valid_actions()[0].clone();
+            game.action(&action);
+            action
+        }
+
+        #[test]
+        fn undo_restores_the_board_score_and_spawns() {
+            let mut game = Game::with_seed(21);
+            game.set_history_depth(10);
+            let start = game.board.clone();
+            let mut played = Vec::new();
+            let mut boards = Vec::new();
+            for _ in 0..5 {
+                played.push(play_any(&mut game));
+                boards.push((game.board.clone(), game.get_score()));
             }
-        
-            new.board = self.board;
-            new.merge_right();
-            if self.board != new.board{
-                return false;
+
+            for _ in 0..5 {
+                assert!(game.undo());
             }
-        
-            new.board = self.board;
-            new.merge_left();
-            if self.board != new.board{
-                return false;
+            assert!(!game.undo());
+            assert_eq!(game.board, start);
+            assert_eq!((game.get_score(), game.moves()), (0, 0));
+
+            // The restored RNG spawns the same tiles, by redo or by replaying
+            assert!(game.redo());
+            assert_eq!((game.board.clone(), game.get_score()), boards[0]);
+            game.undo();
+            for (action, board) in played.iter().zip(boards.iter()) {
+                game.action(action);
+                assert_eq!(&(game.board.clone(), game.get_score()), board);
             }
-            true 
         }
-        
-        pub fn get_valid_actions(&self) -> Vec<Action> {
-            let mut new = self.clone();
-            let mut valid: Vec<Action> = Vec::new();
-            new.merge_up();
-            if self.board != new.board{
-                valid.push(Action::MergeUp);
-            }
-        
-            new.board = self.board;
-            new.merge_down();
-            if self.board != new.board{
-                valid.push(Action::MergeDown);
+
+        #[test]
+        fn a_new_move_clears_redo() {
+            let mut game = Game::with_seed(4);
+            game.set_history_depth(10);
+            play_any(&mut game);
+            play_any(&mut game);
+            assert!(game.undo());
+            assert!(game.undo());
+            assert!(game.redo());
+            play_any(&mut game);
+            assert!(!game.redo());
+            assert!(game.undo());
+        }
+
+        #[test]
+        fn history_is_trimmed_to_its_depth() {
+            let mut game = Game::with_seed(9);
+            game.set_history_depth(3);
+            for _ in 0..6 {
+                play_any(&mut game);
             }
-        
-            new.board = self.board;
-            new.merge_right();
-            if self.board != new.board{
-                valid.push(Action::MergeRight);
+            let undone = (0..6).take_while(|_| game.undo()).count();
+            assert_eq!(undone, 3);
+
+            for _ in 0..3 {
+                game.redo();
             }
-        
-            new.board = self.board;
-            new.merge_left();
-            if self.board != new.board{
-                valid.push(Action::MergeLeft);
+            game.set_history_depth(1);
+            assert!(game.undo());
+            assert!(!game.undo());
+
+            game.set_history_depth(0);
+            play_any(&mut game);
+            assert!(!game.undo());
+        }
+
+        #[test]
+        fn reaching_the_target_wins_once() {
+            let config = GameConfig {
+                win_target: 64,
+                ..GameConfig::default()
+            };
+            let mut game = Game::with_config_and_seed(config, 3);
+            game.set_history_depth(10);
+            game.board = vec![
+                vec![32, 32, 0, 0],
+                vec![16, 16, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+            ]
+            .into();
+            assert!(!game.has_won());
+            assert_eq!(game.action(&Action::MergeLeft), GameState::Won);
+            assert!(game.has_won());
+            assert_eq!(game.max_tile(), 64);
+
+            let next = game.get_valid_actions()[0].clone();
+            assert_eq!(game.action(&next), GameState::Ok);
+
+            game.undo();
+            game.undo();
+            assert!(!game.has_won());
+            assert_eq!(game.action(&Action::MergeLeft), GameState::Won);
+        }
+
+        #[test]
+        fn game_over_keeps_the_final_board() {
+            let mut game = game_with(vec![
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+                vec![2, 4, 2, 16],
+                vec![4, 2, 0, 8],
+            ]);
+            game.score = 100;
+            game.moves = 41;
+            let state = game.action(&Action::MergeLeft);
+            assert!(game.is_gameover(), "{:?}", game.board);
+            assert_eq!(state, GameState::Gameover);
+            assert_eq!(game.board.to_rows()[3][..3], [4, 2, 8]);
+
+            let stats = game.stats();
+            assert_eq!(stats.score, 100);
+            assert_eq!(stats.moves, 42);
+            assert_eq!(stats.max_tile, 16);
+            assert_eq!(stats.seed, game.seed());
+
+            // Further moves leave it alone until the caller resets
+            let board = game.board.clone();
+            assert_eq!(game.action(&Action::MergeUp), GameState::Gameover);
+            assert_eq!(game.board, board);
+
+            game.reset();
+            assert_eq!(game.moves(), 0);
+            assert_eq!(game.get_score(), 0);
+        }
+
+        #[test]
+        fn bitboard_path_matches_generic_path() {
+            let mut rng = ChaCha8Rng::seed_from_u64(11);
+            let actions = [Action::MergeLeft, Action::MergeRight, Action::MergeUp, Action::MergeDown];
+            for _ in 0..2000 {
+                let board: Vec<Vec<u32>> = (0..4)
+                    .map(|_| {
+                        (0..4)
+                            .map(|_| match rng.gen_range(0..12) {
+                                0..=3 => 0,
+                                e => 1 << (e - 2),
+                            })
+                            .collect()
+                    })
+                    .collect();
+                for action in actions.iter() {
+                    let mut fast = game_with(board.clone());
+                    assert!(fast.board.bitboard().is_some());
+                    fast.shift(action);
+
+                    let mut generic = board.clone();
+                    let merged = shift_rows(&mut generic, action);
+                    assert_eq!(fast.board, generic, "{:?} on {:?}", action, board);
+                    assert_eq!(fast.merged_last, merged, "{:?} on {:?}", action, board);
+                }
             }
-            valid
+        }
 
+        #[test]
+        fn keeps_ordinary_4x4_boards_packed() {
+            assert!(Board::empty(4, 4).bitboard().is_some());
+            assert!(Board::empty(3, 5).bitboard().is_none());
+
+            let mut board = Board::empty(4, 4);
+            board.set(0, 0, 3);
+            assert!(board.bitboard().is_none());
+            board.set(0, 0, 2);
+            assert!(board.bitboard().is_some());
+            assert_eq!(board.get(0, 0), 2);
+
+            // Merging the largest exact tiles leaves what the tables can move
+            let mut rows = vec![vec![0; 4]; 4];
+            rows[0][..2].copy_from_slice(&[16384, 16384]);
+            let mut board = Board::from(rows);
+            assert_eq!(board.shift(&Action::MergeLeft), 32768);
+            assert!(board.bitboard().is_none());
+            assert_eq!(board.get(0, 0), 32768);
+
+            // Saved as rows either way
+            let game = Game::with_seed(5);
+            let saved = ron::to_string(&game.board).unwrap();
+            assert_eq!(saved, ron::to_string(&game.board.to_rows()).unwrap());
+            assert_eq!(ron::from_str::<Board>(&saved).unwrap(), game.board);
+        }
+
+        #[test]
+        fn afterstate_slides_without_spawning() {
+            let mut game = game_with(vec![
+                vec![2, 2, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 4],
+            ]);
+            game.set_history_depth(5);
+            game.action(&Action::MergeUp);
+            game.board = vec![
+                vec![2, 2, 0, 4],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+            ]
+            .into();
+
+            let after = game.afterstate(&Action::MergeLeft).unwrap();
+            assert_eq!(after.board.to_rows()[0], vec![4, 4, 0, 0]);
+            assert_eq!(after.remaining_empty(), game.remaining_empty() + 1);
+            assert_eq!(after.get_score(), game.get_score() + 4);
+            assert_eq!(after.prev_board, game.board);
+            assert!(!after.clone().undo());
+
+            assert!(game.afterstate(&Action::MergeUp).is_none());
+        }
+
+        #[test]
+        fn unchanged_board_is_an_invalid_move() {
+            let mut game = game_with(vec![
+                vec![2, 4, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+            ]);
+            assert_eq!(game.action(&Action::MergeLeft), GameState::InvalidMove);
+            assert_eq!(game.board.to_rows()[0], vec![2, 4, 0, 0]);
+            assert_eq!(game.get_score(), 0);
         }
-    
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,195 @@
+pub mod heuristic {
+    //! Position evaluations for the AI players. None of these are the score a
+    //! player sees; that is `Game::get_score`.
+    use clap::ValueEnum;
+    use serde::{Deserialize, Serialize};
+
+    use crate::game::oxydized2048::{Action, Game};
+
+    /// Scores a position; higher is better for the player.
+    pub type Evaluation = fn(&Game) -> f64;
+
+    /// The heuristic Q-learning takes its rewards from, as its change across
+    /// each move. Saved with the model it trained.
+    #[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Reward {
+        #[default]
+        Shaped,
+        Monotonic,
+        Score,
+    }
+
+    impl Reward {
+        pub fn evaluation(self) -> Evaluation {
+            match self {
+                Reward::Shaped => shaped,
+                Reward::Monotonic => monotonic,
+                Reward::Score => score,
+            }
+        }
+    }
+
+    /// Names accepted by `by_name`, for menus and command-line flags.
+    pub const NAMES: [&str; 3] = ["monotonic", "shaped", "score"];
+
+    pub fn by_name(name: &str) -> Option<Evaluation> {
+        match name {
+            "monotonic" => Some(monotonic),
+            "shaped" => Some(shaped),
+            "score" => Some(score),
+            _ => None,
+        }
+    }
+
+    /// The player-facing score, for agents that should optimise it directly.
+    pub fn score(game: &Game) -> f64 {
+        game.get_score() as f64
+    }
+
+    /// Reward-shaping heuristic the Q-learning agent was tuned with: a log2 tile
+    /// sum plus bonuses for keeping the largest tiles together in the bottom-right
+    /// corner, doubled for moves toward that corner.
+    pub fn shaped(game: &Game) -> f64 {
+        let mut score: u32 = 0;
+        let mut max: u32 = 0;
+        let mut maxes: Vec<(usize, usize)> = Vec::new();
+        let mut second_maxes: Vec<(usize, usize)> = Vec::new();
+        let board = &game.board;
+        for row_n in 0..board.rows(){
+            for col_n in 0..board.cols(){
+                let col = board.get(row_n, col_n);
+                if col > max {
+                    maxes.push((row_n, col_n));
+                    
+                    max = col;
+                }
+                score += fast_math::log2(col as f32) as u32;
+                
+            }
+        }
+        for row_n in 0..board.rows(){
+            for col_n in 0..board.cols(){
+                if board.get(row_n, col_n) == max/2{
+                    second_maxes.push((row_n, col_n));
+                }
+            }
+        }
+
+        let mut applied = false;
+        let mut x_prev: usize = 0;
+        let mut y_prev: usize = 0;
+        for (i, (x, y)) in maxes.iter().enumerate(){
+            if i > 0 {
+                if x > &0 {
+                    if x - 1 == x_prev || x + 1 == x_prev && *y == y_prev {
+                        score += max*10;
+
+                    }
+                }
+                if y > &0 {
+                    if y - 1 == y_prev || y + 1 == y_prev && *x == x_prev {
+                        score += max*10;
+                    }
+                }
+            }
+
+
+            if *x == game.rows() - 1 && !applied{
+                //max *= 10;
+                if *y == game.cols() - 1 && !applied {
+                    //println!("max: {}", max);
+                    if max >= 128 {
+                        max *= 10;
+                        applied = true;
+                    
+                    }
+                }
+            }
+            x_prev = *x;
+            y_prev = *y;
+        }
+
+        if maxes.len() == 1 && second_maxes.len() > 1{
+            for (i, (x, y)) in second_maxes.iter().enumerate(){
+                if i > 0 {
+                    if x > &0 {
+                        if x - 1 == x_prev || x + 1 == x_prev && *y == y_prev {
+                            score += (max/2)*10;
+
+                        }
+                    }
+                    if y > &0 {
+                        if y - 1 == y_prev || y + 1 == y_prev && *x == x_prev {
+                            score += (max/2)*10;
+                        }
+                    }
+                }
+                x_prev = *x;
+                y_prev = *y;
+            }
+
+        }
+        /* Additional feature I didn't end up using
+        let row4 = game.board[3].iter().sum::<u32>();
+        let row3 = game.board[2].iter().sum::<u32>();
+        let row2 = game.board[1].iter().sum::<u32>();
+        let row1 = game.board[0].iter().sum::<u32>();
+        if row4 > row3+row2+row1 {
+            score += row4*10;
+            if row3 > row2+row1 {
+                score += row3*10;
+                if row2 > row1 {
+                    score += row2*10;
+                }
+            }
+        }*/
+        if game.last_action == Action::MergeDown || game.last_action == Action::MergeRight {
+            ((max*10) + score*2 + game.merged_last) as f64
+        } else {
+            ((max*10) + score + game.merged_last) as f64
+        }
+    }
+
+    /// Board-only evaluation in the style of the well-known expectimax players:
+    /// every row and column earns points for empty cells and equal neighbours,
+    /// and loses points for breaking monotonic order and for holding large tiles.
+    pub fn monotonic(game: &Game) -> f64 {
+        let board = &game.board;
+        let rows = (0..board.rows()).map(|i| {
+            let row: Vec<u32> = (0..board.cols()).map(|j| board.get(i, j)).collect();
+            line_value(&row)
+        });
+        let cols = (0..board.cols()).map(|j| {
+            let col: Vec<u32> = (0..board.rows()).map(|i| board.get(i, j)).collect();
+            line_value(&col)
+        });
+        rows.chain(cols).sum()
+    }
+
+    fn line_value(line: &[u32]) -> f64 {
+        let ranks: Vec<f64> = line
+            .iter()
+            .map(|tile| if *tile == 0 { 0.0 } else { (*tile as f64).log2() })
+            .collect();
+
+        let empty = ranks.iter().filter(|rank| **rank == 0.0).count() as f64;
+        let sum: f64 = ranks.iter().map(|rank| rank.powf(3.5)).sum();
+
+        let tiles: Vec<f64> = ranks.iter().copied().filter(|rank| *rank > 0.0).collect();
+        let merges = tiles.windows(2).filter(|pair| pair[0] == pair[1]).count() as f64;
+
+        let mut toward_start = 0.0;
+        let mut toward_end = 0.0;
+        for pair in ranks.windows(2) {
+            if pair[0] > pair[1] {
+                toward_start += pair[0].powi(4) - pair[1].powi(4);
+            } else {
+                toward_end += pair[1].powi(4) - pair[0].powi(4);
+            }
+        }
+
+        200000.0 + 270.0 * empty + 700.0 * merges
+            - 47.0 * f64::min(toward_start, toward_end)
+            - 11.0 * sum
+    }
+}
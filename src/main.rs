@@ -1,104 +1,213 @@
 pub mod game;
 pub mod ai;
-
-use crate::game::oxydized2048::{Game, GameState, Action};
+pub mod cli;
+pub mod bitboard;
+pub mod evaluation;
+pub mod exploration;
+pub mod expectimax;
+pub mod heuristic;
+pub mod metrics;
+pub mod model;
+pub mod montecarlo;
+pub mod ntuple;
+pub mod player;
+pub mod qtable;
+pub mod termination;
+pub mod ui;
+
+use crate::game::oxydized2048::GameConfig;
 use crate::ai::ai::*;
-
-use std::collections::HashMap;
+use crate::cli::cli::{load_trainer, parse_board_size, save_trainer, train_q_learning, Cli, LearningArgs};
+use crate::evaluation::evaluation::{available_threads, play_games, Evaluation};
+use crate::expectimax::expectimax::Expectimax;
+use crate::metrics::metrics::MetricsLog;
+use crate::model::model::ModelStore;
+use crate::montecarlo::montecarlo::{MonteCarlo, Rollouts};
+use crate::ntuple::ntuple::NTupleNetwork;
+use crate::player::player::{play_game, Report};
+use crate::termination::termination::Limits;
+use crate::ui::ui::play;
+
+use std::error::Error;
+use std::path::Path;
 use std::process;
+use std::time::Duration;
 
+use clap::Parser;
 use requestty::{Question};
-use rurel::AgentTrainer;
 
 fn main() {
+    let result = match Cli::parse().command {
+        Some(command) => cli::cli::run(command),
+        None => menu(),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn menu() -> Result<(), Box<dyn Error>> {
     let human_or_machine  = Question::select("Human or machine")
         .message("What do you want to do?")
         .choice("Play")
         .choice("Train")
         .choice("Train and Test")
         .choice("Test")
+        .choice("Expectimax")
+        .choice("Monte Carlo")
+        .choice("Train N-tuple")
+        .choice("Test N-tuple")
         .build();
     
     let binding = &requestty::prompt_one(human_or_machine);
     let answer = answer_or_exit(binding).as_list_item().unwrap().text.as_str();
+
+    let board_size = Question::input("Board size")
+        .message("Board size (rows x cols)")
+        .default("4x4")
+        .validate(|size, _| match parse_board_size(size) {
+            Some(_) => Ok(()),
+            None => Err("Enter a size like 4x4, 5x5 or 3x6".to_owned()),
+        })
+        .build();
+    let binding = &requestty::prompt_one(board_size);
+    let (rows, cols) = parse_board_size(answer_or_exit(binding).as_string().unwrap()).unwrap();
+    let config = GameConfig::with_size(rows, cols);
     
     match answer {
         "Play" => {
-            play();
+            play(config, None)?;
         },
         "Train" => {
-            /*println!("Loading learned state from file...");
-            let learned_state: HashMap<Game, HashMap<Action, f64>> = serde_any::from_file("learned_state.ron").unwrap();
-            let mut trainer: AgentTrainer<Game> = AgentTrainer::new();
-
-            println!("Importing state...");
-            trainer.import_state(learned_state);*/
-
-            let mut trainer = AgentTrainer::new();
-            let trainer = train(&mut trainer, 0.2, 0.6, 0.5, 10000, 1000);
-            let learned_state = trainer.export_learned_values();
-
-            println!("Saving learned state to file...");
-            serde_any::to_file("learned_state.ron", &learned_state).unwrap();
+            let store = ModelStore::new(LEARNED_STATE)?;
+            let learning = LearningArgs::default();
+            let schedule = Schedule {
+                iterations: learning.iterations,
+                runs: Some(1000),
+                limits: Limits::default(),
+                threads: 1,
+                checkpoint_every: 100,
+                seed: None,
+            };
+            train_q_learning(&store, &learning, &schedule, &config, false, &mut MetricsLog::new(None, 100)?)?;
         },
         "Train and Test" => {
-            println!("Loading learned state from file...");
-            let learned_state: HashMap<Game, HashMap<Action, f64>> = serde_any::from_file("learned_state.ron").unwrap();
-            let mut trainer: AgentTrainer<Game> = AgentTrainer::new();
-
-            println!("Importing state...");
-            trainer.import_state(learned_state);
-
-            let (trainer, high_score) = test_and_train(&mut trainer, 0.2, 0.6, 0.5, 10000, 1);
+            let store = ModelStore::new(LEARNED_STATE)?;
+            let (mut trainer, mut metadata) = load_trainer(&store)?;
+            stop_on_interrupt();
+
+            let learning = LearningArgs::default();
+            metadata.hyperparameters = learning.resumed(&metadata.hyperparameters)?;
+            let (trainer, high_score, steps) = test_and_train(
+                &mut trainer,
+                &metadata.hyperparameters,
+                learning.iterations,
+                1,
+                &Limits::default(),
+                &config,
+                None,
+            );
             println!("High score: {}", high_score);
 
-            let learned_state = trainer.export_learned_values();
-
-            println!("Saving learned state to file...");
-            serde_any::to_file("learned_state.ron", &learned_state).unwrap();
+            metadata.iterations += steps;
+            save_trainer(trainer, metadata, &store)?;
         },
         "Test" => {
-            println!("Loading learned state from file...");
-            let learned_state: HashMap<Game, HashMap<Action, f64>> = serde_any::from_file("learned_state.ron").unwrap();
-            let mut trainer: AgentTrainer<Game> = AgentTrainer::new();
-
-            println!("Importing state...");
-            trainer.import_state(learned_state);
+            let (trainer, _) = load_trainer(&ModelStore::new(LEARNED_STATE)?)?;
+            let (records, elapsed) = play_games(|_| &trainer, &config, 1000, None, available_threads(), Report::Result);
+            println!("{}", Evaluation::new("q-learning", &config, None, &records, elapsed).summary);
+        },
+        "Expectimax" => {
+            let depth = Question::int("Depth")
+                .message("Search depth (moves)")
+                .default(2)
+                .build();
+            let binding = &requestty::prompt_one(depth);
+            let depth = answer_or_exit(binding).as_int().unwrap().max(1) as u32;
+
+            let evaluation = Question::select("Heuristic")
+                .message("Board heuristic")
+                .choice("monotonic")
+                .choice("shaped")
+                .choice("score")
+                .build();
+            let binding = &requestty::prompt_one(evaluation);
+            let evaluation = answer_or_exit(binding).as_list_item().unwrap().text.as_str();
+            let evaluation = heuristic::heuristic::by_name(evaluation).unwrap();
+
+            let mut player = Expectimax::new(depth, evaluation);
             for _ in 0..1000 {
-                let high_score = test(&trainer);
-                println!("High score: {}", high_score);
+                let record = play_game(&mut player, &config, Report::Progress(100));
+                println!("Score: {}", record.stats.score);
             }
         },
+        "Monte Carlo" => {
+            let budget = Question::select("Budget")
+                .message("Rollout budget per move")
+                .choice("Playouts")
+                .choice("Time")
+                .build();
+            let binding = &requestty::prompt_one(budget);
+            let budget = answer_or_exit(binding).as_list_item().unwrap().text.as_str();
+            let rollouts = if budget == "Time" {
+                let millis = Question::int("Millis")
+                    .message("Milliseconds per move")
+                    .default(100)
+                    .build();
+                let binding = &requestty::prompt_one(millis);
+                Rollouts::Time(Duration::from_millis(answer_or_exit(binding).as_int().unwrap().max(1) as u64))
+            } else {
+                let playouts = Question::int("Playouts")
+                    .message("Playouts per action")
+                    .default(100)
+                    .build();
+                let binding = &requestty::prompt_one(playouts);
+                Rollouts::Playouts(answer_or_exit(binding).as_int().unwrap().max(1) as u32)
+            };
+
+            let threads = available_threads();
+            let mut player = MonteCarlo::new(rollouts, threads);
+            for _ in 0..1000 {
+                let record = play_game(&mut player, &config, Report::Progress(100));
+                println!("Score: {}", record.stats.score);
+            }
+        },
+        "Train N-tuple" | "Test N-tuple" if (rows, cols) != (4, 4) => {
+            println!("The n-tuple network only plays 4x4 boards");
+            process::exit(1);
+        },
+        "Train N-tuple" => {
+            let mut network = if Path::new(ntuple::ntuple::WEIGHTS_FILE).exists() {
+                println!("Loading weights from file...");
+                NTupleNetwork::load(ntuple::ntuple::WEIGHTS_FILE)?
+            } else {
+                NTupleNetwork::new()
+            };
+
+            let episodes = Question::int("Episodes")
+                .message("Training games")
+                .default(10000)
+                .build();
+            let binding = &requestty::prompt_one(episodes);
+            let episodes = answer_or_exit(binding).as_int().unwrap().max(1) as u64;
+
+            ntuple::ntuple::train(&mut network, 0.1, &Limits::episodes(episodes), 1000, &config, None);
+
+            println!("Saving weights to file...");
+            network.save(ntuple::ntuple::WEIGHTS_FILE)?;
+        },
+        "Test N-tuple" => {
+            println!("Loading weights from file...");
+            let network = NTupleNetwork::load(ntuple::ntuple::WEIGHTS_FILE)?;
+            let (records, elapsed) = play_games(|_| &network, &config, 1000, None, available_threads(), Report::Result);
+            println!("{}", Evaluation::new("ntuple", &config, None, &records, elapsed).summary);
+        },
         _ => process::exit(1),
     }
-    
-    
+    Ok(())
 }
 
-fn play(){
-    let mut game = Game::new();
-    let mut state = GameState::Ok;
-    
-
-    while state != GameState::Gameover {
-        loop {
-            game.display();
-
-            let mut line = String::new();
-            let _ = std::io::stdin().read_line(&mut line).unwrap();
-            //println!("{}", line.len());
-
-            state = char_to_action(&line, &mut game);
-
-            if state != GameState::InvalidMove {
-                break;
-            }
-            println!("Invalid move, please try again.");
-        }
-
-        println!("Score: {:?}", game.get_score());
-    }
-}
 fn answer_or_exit(
     binding: &std::result::Result<requestty::Answer, requestty::ErrorKind>,
 ) -> &requestty::Answer {
@@ -114,12 +223,3 @@ fn answer_or_exit(
     answer
 }
 
-fn char_to_action(char: &String, game: &mut Game) -> GameState {
-    match char.trim() {
-        "w" => game.action(&Action::MergeUp),
-        "a" => game.action(&Action::MergeLeft),
-        "s" => game.action(&Action::MergeDown),
-        "d" => game.action(&Action::MergeRight),
-        _ => GameState::InvalidMove
-    }
-}
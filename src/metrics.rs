@@ -0,0 +1,208 @@
+pub mod metrics {
+    //! Per-episode training metrics, logged to CSV or JSON Lines under an ID
+    //! for the training run, with moving averages printed as training goes.
+    //! Both formats append, so one file can collect many runs.
+    use std::collections::VecDeque;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, BufWriter, Write};
+    use std::path::Path;
+
+    use chrono::Utc;
+    use serde::{Deserialize, Serialize};
+
+    const CSV_HEADER: &str = "run_id,episode,steps,score,max_tile,reward,table_size,seconds";
+
+    /// One finished training game.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Episode {
+        pub steps: u32,
+        /// The game's own score, not the shaped reward.
+        pub score: u32,
+        pub max_tile: u32,
+        /// The sum of the rewards the learner was given.
+        pub reward: f64,
+        /// States in the Q-table when the game ended.
+        pub table_size: usize,
+        /// Wall time since training started.
+        pub seconds: f64,
+    }
+
+    #[derive(Serialize)]
+    struct Row<'a> {
+        run_id: &'a str,
+        episode: u64,
+        #[serde(flatten)]
+        metrics: &'a Episode,
+    }
+
+    enum Sink {
+        Csv(BufWriter<File>),
+        Jsonl(BufWriter<File>),
+    }
+
+    pub struct MetricsLog {
+        run_id: String,
+        sink: Option<Sink>,
+        recent: VecDeque<Episode>,
+        window: usize,
+        episodes: u64,
+        // Episodes since the averages were last printed
+        unreported: usize,
+    }
+
+    impl MetricsLog {
+        /// Starts a run ID from the current time and, given a path, appends
+        /// every episode to it as `.csv` or `.jsonl`. Moving averages are over
+        /// the last `window` episodes and printed every `window` episodes.
+        pub fn new(path: Option<&Path>, window: usize) -> io::Result<MetricsLog> {
+            let sink = match path {
+                Some(path) => {
+                    let csv = match path.extension().and_then(|extension| extension.to_str()) {
+                        Some("csv") => true,
+                        Some("jsonl") => false,
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidInput,
+                                "metrics are saved as .csv or .jsonl",
+                            ))
+                        }
+                    };
+                    let new = !path.exists() || path.metadata()?.len() == 0;
+                    let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+                    if csv {
+                        if new {
+                            writeln!(file, "{}", CSV_HEADER)?;
+                        }
+                        Some(Sink::Csv(file))
+                    } else {
+                        Some(Sink::Jsonl(file))
+                    }
+                }
+                None => None,
+            };
+            Ok(MetricsLog {
+                run_id: Utc::now().format("%Y%m%d-%H%M%S").to_string(),
+                sink,
+                recent: VecDeque::new(),
+                window: window.max(1),
+                episodes: 0,
+                unreported: 0,
+            })
+        }
+
+        pub fn run_id(&self) -> &str {
+            &self.run_id
+        }
+
+        pub fn episodes(&self) -> u64 {
+            self.episodes
+        }
+
+        /// Logs `episode`, printing the moving averages when a window fills. If
+        /// the file can't be written the error is returned once and the log
+        /// carries on printing without it.
+        pub fn record(&mut self, episode: Episode) -> io::Result<()> {
+            self.episodes += 1;
+            let written = match &mut self.sink {
+                Some(Sink::Csv(file)) => writeln!(
+                    file,
+                    "{},{},{},{},{},{},{},{}",
+                    self.run_id,
+                    self.episodes,
+                    episode.steps,
+                    episode.score,
+                    episode.max_tile,
+                    episode.reward,
+                    episode.table_size,
+                    episode.seconds
+                ),
+                Some(Sink::Jsonl(file)) => {
+                    let row = Row {
+                        run_id: &self.run_id,
+                        episode: self.episodes,
+                        metrics: &episode,
+                    };
+                    serde_json::to_writer(&mut *file, &row)
+                        .map_err(io::Error::from)
+                        .and_then(|_| writeln!(file))
+                }
+                None => Ok(()),
+            };
+            if written.is_err() {
+                self.sink = None;
+            }
+
+            if self.recent.len() == self.window {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(episode);
+            self.unreported += 1;
+            if self.unreported == self.window {
+                self.unreported = 0;
+                println!("{}", self.summary());
+            }
+            written
+        }
+
+        /// Moving averages over the last `window` episodes.
+        pub fn summary(&self) -> String {
+            let count = self.recent.len().max(1) as f64;
+            let mean = |value: fn(&Episode) -> f64| self.recent.iter().map(value).sum::<f64>() / count;
+            format!(
+                "Episode: {} | Last {}: mean score {:.0}, mean length {:.0}, mean reward {:.1}, best tile {} | Q-table: {} states",
+                self.episodes,
+                self.recent.len(),
+                mean(|episode| episode.score as f64),
+                mean(|episode| episode.steps as f64),
+                mean(|episode| episode.reward),
+                self.recent.iter().map(|episode| episode.max_tile).max().unwrap_or(0),
+                self.recent.back().map(|episode| episode.table_size).unwrap_or(0)
+            )
+        }
+
+        pub fn flush(&mut self) -> io::Result<()> {
+            match &mut self.sink {
+                Some(Sink::Csv(file)) | Some(Sink::Jsonl(file)) => file.flush(),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::temp_path;
+
+        fn episode(score: u32) -> Episode {
+            Episode {
+                steps: score / 10,
+                score,
+                max_tile: 256,
+                reward: 1.5,
+                table_size: score as usize,
+                seconds: 0.25,
+            }
+        }
+
+        #[test]
+        fn appends_rows_and_averages_a_window() {
+            let path = temp_path("metrics.jsonl");
+            let mut log = MetricsLog::new(Some(&path), 2).unwrap();
+            for score in [100, 200, 400] {
+                log.record(episode(score)).unwrap();
+            }
+            log.flush().unwrap();
+            assert!(log.summary().contains("mean score 300"));
+
+            let text = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let rows: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+            assert_eq!(rows.len(), 3);
+            assert_eq!(rows[2]["run_id"], log.run_id());
+            assert_eq!(rows[2]["episode"], 3);
+            assert_eq!(rows[2]["score"], 400);
+
+            assert!(MetricsLog::new(Some(Path::new("metrics.txt")), 2).is_err());
+        }
+    }
+}
@@ -0,0 +1,567 @@
+pub mod model {
+    //! Saving and loading learned state. A `ModelStore` wraps one path, picks
+    //! the format from its extension and never leaves a half-written file
+    //! behind: every save goes to a temporary file that is renamed into place.
+    //! Q-tables can also go in the compact `.qtable` format from `qtable`.
+    use std::collections::hash_map::Entry;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::fmt;
+    use std::fs::{self, File};
+    use std::hash::Hash;
+    use std::io::{self, BufReader, BufWriter};
+    use std::path::{Path, PathBuf};
+
+    use chrono::{DateTime, Utc};
+    use serde::de::DeserializeOwned;
+    use serde::{Deserialize, Serialize};
+
+    use crate::ai::ai::LearningState;
+    use crate::exploration::exploration::Exploration;
+    use crate::game::oxydized2048::{Action, GameConfig};
+    use crate::heuristic::heuristic::{Evaluation, Reward};
+    use crate::qtable::qtable::{self, Board};
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Format {
+        Ron,
+        Json,
+        Bincode,
+        /// Only for `Model`s; see `qtable`.
+        QTable,
+    }
+
+    impl Format {
+        /// `.ron`, `.json`, `.bin`/`.bincode` or `.qtable`.
+        pub fn from_path(path: &Path) -> Option<Format> {
+            match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+                "ron" => Some(Format::Ron),
+                "json" => Some(Format::Json),
+                "bin" | "bincode" => Some(Format::Bincode),
+                "qtable" => Some(Format::QTable),
+                _ => None,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ModelError {
+        UnknownFormat(PathBuf),
+        NotFound(PathBuf),
+        Io(PathBuf, io::Error),
+        Encode(PathBuf, String),
+        Decode(PathBuf, String),
+    }
+
+    impl fmt::Display for ModelError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                ModelError::UnknownFormat(path) => write!(
+                    f,
+                    "can't tell the format of {}: use a .ron, .json, .bin or .qtable extension",
+                    path.display()
+                ),
+                ModelError::NotFound(path) => write!(f, "no model at {}", path.display()),
+                ModelError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+                ModelError::Encode(path, e) => write!(f, "couldn't write {}: {}", path.display(), e),
+                ModelError::Decode(path, e) => write!(
+                    f,
+                    "{} is corrupt or not a model of this version: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    }
+
+    impl Error for ModelError {}
+
+    /// The Q-learning settings a model was trained with.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Hyperparameters {
+        pub alpha: f64,
+        pub gamma: f64,
+        pub initial_value: f64,
+        /// Random for models saved before it was recorded.
+        #[serde(default)]
+        pub exploration: Exploration,
+        /// Shaped for models saved before it was recorded.
+        #[serde(default)]
+        pub reward: Reward,
+    }
+
+    impl Hyperparameters {
+        pub fn evaluation(&self) -> Evaluation {
+            self.reward.evaluation()
+        }
+    }
+
+    /// The settings Q-learning was hardcoded with before they could be set.
+    impl Default for Hyperparameters {
+        fn default() -> Hyperparameters {
+            Hyperparameters {
+                alpha: 0.2,
+                gamma: 0.6,
+                initial_value: 0.5,
+                exploration: Exploration::Random,
+                reward: Reward::Shaped,
+            }
+        }
+    }
+
+    /// A path in the temp directory unique to this test process, for tests
+    /// that write files.
+    #[cfg(test)]
+    pub fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty2048-test-{}-{}", std::process::id(), name))
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Metadata {
+        pub created: DateTime<Utc>,
+        pub saved: DateTime<Utc>,
+        pub hyperparameters: Hyperparameters,
+        /// Training steps taken over the model's whole life.
+        pub iterations: u64,
+        pub config: GameConfig,
+    }
+
+    impl Metadata {
+        pub fn new(hyperparameters: Hyperparameters, config: GameConfig) -> Metadata {
+            let now = Utc::now();
+            Metadata {
+                created: now,
+                saved: now,
+                hyperparameters,
+                iterations: 0,
+                config,
+            }
+        }
+    }
+
+    pub type Values = HashMap<LearningState, HashMap<Action, f64>>;
+
+    /// A Q-table and where it came from. The table is kept as a list rather
+    /// than a map so formats with string-only keys, like JSON, can hold it.
+    ///
+    /// Tables saved while states were whole `Game`s still load from RON and
+    /// JSON, since only the board is read back; their repeated boards are
+    /// merged by `learned_values` and `board_values`.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Model {
+        pub metadata: Metadata,
+        pub values: Vec<(LearningState, Vec<(Action, f64)>)>,
+    }
+
+    impl Model {
+        pub fn new(metadata: Metadata, learned_values: Values) -> Model {
+            let values = learned_values
+                .into_iter()
+                .map(|(state, actions)| (state, actions.into_iter().collect()))
+                .collect();
+            Model { metadata, values }
+        }
+
+        /// The values by board. A board listed more than once gets the mean
+        /// of each action's values.
+        pub fn board_values(&self) -> HashMap<Board, HashMap<Action, f64>> {
+            merge_by_board(
+                self.values
+                    .iter()
+                    .map(|(state, actions)| (state.board.to_rows(), actions.iter().cloned().collect())),
+            )
+        }
+
+        fn from_board_values<I>(metadata: Metadata, entries: I) -> Model
+        where
+            I: IntoIterator<Item = (Board, HashMap<Action, f64>)>,
+        {
+            let values = entries
+                .into_iter()
+                .map(|(board, actions)| (LearningState::from_board(board.into()), actions.into_iter().collect()))
+                .collect();
+            Model { metadata, values }
+        }
+
+        /// The table in the shape `AgentTrainer::import_state` takes, with
+        /// repeated boards merged as in `board_values`.
+        pub fn learned_values(&self) -> Values {
+            collect_values(
+                self.values
+                    .iter()
+                    .map(|(state, actions)| (state.clone(), actions.iter().cloned().collect())),
+            )
+        }
+
+        /// Like `learned_values`, but gives up the list while building the map.
+        pub fn into_learned_values(self) -> (Metadata, Values) {
+            let values = self
+                .values
+                .into_iter()
+                .map(|(state, actions)| (state, actions.into_iter().collect()));
+            (self.metadata, collect_values(values))
+        }
+    }
+
+    // Builds the map in place, merging repeated boards as `merge_by_board`
+    // does. Only the repeated boards are gathered on the side, so a table
+    // listing each board once is never held twice.
+    fn collect_values<I>(entries: I) -> Values
+    where
+        I: IntoIterator<Item = (LearningState, HashMap<Action, f64>)>,
+    {
+        let mut values = Values::new();
+        let mut repeated = Vec::new();
+        for (state, actions) in entries {
+            match values.entry(state) {
+                Entry::Vacant(entry) => {
+                    entry.insert(actions);
+                }
+                Entry::Occupied(entry) => repeated.push((entry.key().board.clone(), actions)),
+            }
+        }
+        if repeated.is_empty() {
+            return values;
+        }
+        let mut firsts = Vec::new();
+        for (board, _) in repeated.iter() {
+            if let Some((state, actions)) = values.remove_entry(&LearningState::from_board(board.clone())) {
+                firsts.push((state.board, actions));
+            }
+        }
+        for (board, actions) in merge_by_board(firsts.into_iter().chain(repeated)) {
+            values.insert(LearningState::from_board(board), actions);
+        }
+        values
+    }
+
+    /// Merges values of the same board, averaging each action over the
+    /// entries that have a value for it.
+    pub fn merge_by_board<B, I>(entries: I) -> HashMap<B, HashMap<Action, f64>>
+    where
+        B: Hash + Eq,
+        I: IntoIterator<Item = (B, HashMap<Action, f64>)>,
+    {
+        let mut sums: HashMap<B, HashMap<Action, (f64, u32)>> = HashMap::new();
+        for (board, actions) in entries {
+            let sum = sums.entry(board).or_default();
+            for (action, value) in actions {
+                let (total, count) = sum.entry(action).or_insert((0.0, 0));
+                *total += value;
+                *count += 1;
+            }
+        }
+        sums.into_iter()
+            .map(|(board, actions)| {
+                let means = actions
+                    .into_iter()
+                    .map(|(action, (total, count))| (action, total / count as f64))
+                    .collect();
+                (board, means)
+            })
+            .collect()
+    }
+
+    // The Game Q-tables were keyed by while the board was a fixed 4x4 array.
+    // Every field takes part in Hash/Eq, as it did then, so no entry is lost
+    // before they are merged by board.
+    #[derive(PartialEq, Eq, Hash, Deserialize)]
+    struct LegacyGame {
+        board: [[u32; 4]; 4],
+        prev_board: [[u32; 4]; 4],
+        score: u32,
+        prev_score: u32,
+        merged_last: u32,
+        last_action: Action,
+    }
+
+    /// Reads a `learned_state.ron` written before models had metadata. The
+    /// hyperparameters are the ones that were hardcoded at the time.
+    pub fn load_legacy(path: &Path) -> Result<Model, ModelError> {
+        let file = File::open(path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ModelError::NotFound(path.to_owned()),
+            _ => ModelError::Io(path.to_owned(), e),
+        })?;
+        let legacy: HashMap<LegacyGame, HashMap<Action, f64>> = ron::de::from_reader(BufReader::new(file))
+            .map_err(|e| ModelError::Decode(path.to_owned(), e.to_string()))?;
+
+        let metadata = Metadata::new(Hyperparameters::default(), GameConfig::default());
+        let entries = legacy
+            .into_iter()
+            .map(|(game, actions)| (game.board.iter().map(|row| row.to_vec()).collect(), actions));
+        Ok(Model::from_board_values(metadata, merge_by_board(entries)))
+    }
+
+    pub struct ModelStore {
+        path: PathBuf,
+        format: Format,
+    }
+
+    impl ModelStore {
+        pub fn new<P: Into<PathBuf>>(path: P) -> Result<ModelStore, ModelError> {
+            let path = path.into();
+            match Format::from_path(&path) {
+                Some(format) => Ok(ModelStore { path, format }),
+                None => Err(ModelError::UnknownFormat(path)),
+            }
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+
+        pub fn format(&self) -> Format {
+            self.format
+        }
+
+        pub fn exists(&self) -> bool {
+            self.path.exists()
+        }
+
+        fn open(&self) -> Result<BufReader<File>, ModelError> {
+            match File::open(&self.path) {
+                Ok(file) => Ok(BufReader::new(file)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Err(ModelError::NotFound(self.path.clone())),
+                Err(e) => Err(ModelError::Io(self.path.clone(), e)),
+            }
+        }
+
+        pub fn load<T: DeserializeOwned>(&self) -> Result<T, ModelError> {
+            let reader = self.open()?;
+            let decoded = match self.format {
+                Format::Ron => ron::de::from_reader(reader).map_err(|e| e.to_string()),
+                Format::Json => serde_json::from_reader(reader).map_err(|e| e.to_string()),
+                Format::Bincode => bincode::deserialize_from(reader).map_err(|e| e.to_string()),
+                Format::QTable => Err("a .qtable file only holds a Q-table".to_owned()),
+            };
+            decoded.map_err(|e| ModelError::Decode(self.path.clone(), e))
+        }
+
+        pub fn save<T: Serialize>(&self, value: &T) -> Result<(), ModelError> {
+            self.write_atomically(|writer| match self.format {
+                Format::Ron => ron::ser::to_writer(writer, value).map_err(|e| e.to_string()),
+                Format::Json => serde_json::to_writer(writer, value).map_err(|e| e.to_string()),
+                Format::Bincode => bincode::serialize_into(writer, value).map_err(|e| e.to_string()),
+                Format::QTable => Err("a .qtable file only holds a Q-table".to_owned()),
+            })
+        }
+
+        /// Like `load`, but also reads `.qtable` files, streaming their entries.
+        pub fn load_model(&self) -> Result<Model, ModelError> {
+            if self.format != Format::QTable {
+                return self.load();
+            }
+            let mut failed = None;
+            let (metadata, entries) = self.read_qtable(&mut failed)?;
+            let model = Model::from_board_values(metadata, entries);
+            self.check_read(failed)?;
+            Ok(model)
+        }
+
+        /// The metadata and the table in the shape `AgentTrainer::import_state`
+        /// takes. A `.qtable` file's entries go straight into the table.
+        pub fn load_learned_values(&self) -> Result<(Metadata, Values), ModelError> {
+            if self.format != Format::QTable {
+                return self.load().map(Model::into_learned_values);
+            }
+            let mut failed = None;
+            let (metadata, entries) = self.read_qtable(&mut failed)?;
+            let values = collect_values(entries.map(|(board, actions)| (LearningState::from_board(board.into()), actions)));
+            self.check_read(failed)?;
+            Ok((metadata, values))
+        }
+
+        // Opens a `.qtable` file for its entries, which end at the first one
+        // that fails to read, leaving the error in `failed`.
+        fn read_qtable<'a>(
+            &self,
+            failed: &'a mut Option<io::Error>,
+        ) -> Result<(Metadata, impl Iterator<Item = qtable::Entry> + 'a), ModelError> {
+            let reader = qtable::Reader::new(self.open()?).map_err(|e| self.decode_error(e))?;
+            let metadata = reader.metadata().clone();
+            let entries = reader.map_while(move |entry| entry.map_err(|e| *failed = Some(e)).ok());
+            Ok((metadata, entries))
+        }
+
+        fn check_read(&self, failed: Option<io::Error>) -> Result<(), ModelError> {
+            match failed {
+                Some(e) => Err(self.decode_error(e)),
+                None => Ok(()),
+            }
+        }
+
+        fn decode_error(&self, e: io::Error) -> ModelError {
+            ModelError::Decode(self.path.clone(), e.to_string())
+        }
+
+        /// Like `save`, but also writes `.qtable` files, where values are kept
+        /// by board alone. Entries are written as listed, so a model with
+        /// repeated boards keeps them until it is loaded.
+        pub fn save_model(&self, model: &Model) -> Result<(), ModelError> {
+            if self.format != Format::QTable {
+                return self.save(model);
+            }
+            let config = &model.metadata.config;
+            self.write_atomically(|writer| {
+                let mut table = qtable::Writer::new(writer, &model.metadata, config.rows, config.cols)
+                    .map_err(|e| e.to_string())?;
+                for (state, actions) in model.values.iter() {
+                    table.write(&state.board.to_rows(), actions).map_err(|e| e.to_string())?;
+                }
+                table.finish().map(|_| ()).map_err(|e| e.to_string())
+            })
+        }
+
+        // Writes next to the target and renames over the target once complete,
+        // so a crash mid-save keeps the previous file.
+        fn write_atomically<F>(&self, encode: F) -> Result<(), ModelError>
+        where
+            F: FnOnce(&mut BufWriter<File>) -> Result<(), String>,
+        {
+            let mut temporary = self.path.clone().into_os_string();
+            temporary.push(".tmp");
+            let temporary = PathBuf::from(temporary);
+            let io_error = |e| ModelError::Io(temporary.clone(), e);
+
+            let file = File::create(&temporary).map_err(io_error)?;
+            let mut writer = BufWriter::new(file);
+            let encoded = encode(&mut writer);
+            if let Err(e) = encoded {
+                drop(writer);
+                let _ = fs::remove_file(&temporary);
+                return Err(ModelError::Encode(self.path.clone(), e));
+            }
+            let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+            file.sync_all().map_err(io_error)?;
+            fs::rename(&temporary, &self.path).map_err(|e| ModelError::Io(self.path.clone(), e))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::game::oxydized2048::Game;
+
+        fn model() -> Model {
+            let state = LearningState::from_board(Game::with_seed(8).board);
+            let mut actions = HashMap::new();
+            actions.insert(Action::MergeDown, 1.5);
+            actions.insert(Action::MergeRight, -0.25);
+            let mut values = HashMap::new();
+            values.insert(state, actions);
+
+            let mut metadata = Metadata::new(Hyperparameters::default(), GameConfig::default());
+            metadata.iterations = 1234;
+            Model::new(metadata, values)
+        }
+
+        #[test]
+        fn round_trips_every_format() {
+            for extension in ["ron", "json", "bin"] {
+                let path = temp_path(&format!("model.{}", extension));
+                let store = ModelStore::new(&path).unwrap();
+                let saved = model();
+                store.save(&saved).unwrap();
+                let loaded: Model = store.load().unwrap();
+                fs::remove_file(&path).unwrap();
+
+                assert_eq!(loaded.metadata, saved.metadata);
+                assert!(loaded.learned_values() == saved.learned_values());
+            }
+        }
+
+        #[test]
+        fn qtable_merges_repeated_boards_on_load() {
+            let mut saved = model();
+            let (state, _) = saved.values[0].clone();
+            saved.values.push((state.clone(), vec![(Action::MergeDown, 0.5), (Action::MergeUp, 2.0)]));
+
+            let path = temp_path("model.qtable");
+            let store = ModelStore::new(&path).unwrap();
+            store.save_model(&saved).unwrap();
+            let loaded = store.load_model().unwrap();
+            let (metadata, values) = store.load_learned_values().unwrap();
+            fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.metadata, saved.metadata);
+            assert_eq!(metadata, saved.metadata);
+            assert!(values == loaded.learned_values());
+            assert_eq!(values.len(), 1);
+            let values = &values[&state];
+            assert_eq!(values[&Action::MergeDown], 1.0);
+            assert_eq!(values[&Action::MergeRight], -0.25);
+            assert_eq!(values[&Action::MergeUp], 2.0);
+            assert!(store.save(&saved.metadata).is_err());
+        }
+
+        #[test]
+        fn merges_tables_keyed_by_whole_games() {
+            #[derive(Serialize)]
+            struct GameModel {
+                metadata: Metadata,
+                values: Vec<(Game, Vec<(Action, f64)>)>,
+            }
+
+            let game = Game::with_seed(3);
+            let mut moved_on = game.clone();
+            moved_on.score = 64;
+            moved_on.last_action = Action::MergeLeft;
+            let old = GameModel {
+                metadata: model().metadata,
+                values: vec![
+                    (game.clone(), vec![(Action::MergeDown, 1.0)]),
+                    (moved_on, vec![(Action::MergeDown, 3.0), (Action::MergeUp, -1.0)]),
+                ],
+            };
+
+            for extension in ["ron", "json"] {
+                let path = temp_path(&format!("games.{}", extension));
+                let store = ModelStore::new(&path).unwrap();
+                store.save(&old).unwrap();
+                let loaded = store.load_model();
+                fs::remove_file(&path).unwrap();
+
+                let values = loaded.unwrap().learned_values();
+                assert_eq!(values.len(), 1);
+                let actions = &values[&LearningState::from_board(game.board.clone())];
+                assert_eq!(actions[&Action::MergeDown], 2.0);
+                assert_eq!(actions[&Action::MergeUp], -1.0);
+            }
+        }
+
+        #[test]
+        fn converts_legacy_files() {
+            let board = "board:((0,2,0,0),(0,0,0,0),(0,0,0,0),(0,0,0,2))";
+            let empty = "((0,0,0,0),(0,0,0,0),(0,0,0,0),(0,0,0,0))";
+            let legacy = format!(
+                "{{({},prev_board:{},score:0,prev_score:0,merged_last:0,last_action:MergeDown):{{MergeDown:1.0}},\
+                 ({},prev_board:{},score:4,prev_score:0,merged_last:4,last_action:MergeRight):{{MergeDown:3.0,MergeRight:2.0}}}}",
+                board, empty, board, empty
+            );
+            let path = temp_path("legacy.ron");
+            fs::write(&path, legacy).unwrap();
+            let model = load_legacy(&path);
+            fs::remove_file(&path).unwrap();
+
+            let values = model.unwrap().board_values();
+            assert_eq!(values.len(), 1);
+            let board = vec![vec![0, 2, 0, 0], vec![0; 4], vec![0; 4], vec![0, 0, 0, 2]];
+            assert_eq!(values[&board][&Action::MergeDown], 2.0);
+            assert_eq!(values[&board][&Action::MergeRight], 2.0);
+        }
+
+        #[test]
+        fn reports_bad_paths_and_files() {
+            assert!(matches!(ModelStore::new("model.txt"), Err(ModelError::UnknownFormat(_))));
+
+            let path = temp_path("missing.ron");
+            let store = ModelStore::new(&path).unwrap();
+            assert!(matches!(store.load::<Model>(), Err(ModelError::NotFound(_))));
+
+            fs::write(&path, "not a model").unwrap();
+            let loaded = store.load::<Model>();
+            fs::remove_file(&path).unwrap();
+            assert!(matches!(loaded, Err(ModelError::Decode(_, _))));
+        }
+    }
+}
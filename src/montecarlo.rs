@@ -0,0 +1,172 @@
+pub mod montecarlo {
+    //! A rollout player: tries every valid move, finishes the game from there
+    //! with uniformly random moves many times over, and keeps the move whose
+    //! playouts end with the best mean score.
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::game::oxydized2048::{Action, Game};
+    use crate::player::player::Player;
+
+    /// How much simulation to spend on each move.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Rollouts {
+        /// Playouts per valid action, shared out between the threads.
+        Playouts(u32),
+        /// Wall-clock time per move. Every action gets at least one playout.
+        Time(Duration),
+    }
+
+    pub struct MonteCarlo {
+        rollouts: Rollouts,
+        threads: usize,
+        // Seeds the workers, so a seeded player replays the same choices
+        rng: ChaCha8Rng,
+    }
+
+    impl MonteCarlo {
+        pub fn new(rollouts: Rollouts, threads: usize) -> MonteCarlo {
+            MonteCarlo::with_seed(rollouts, threads, rand::thread_rng().gen())
+        }
+
+        pub fn with_seed(rollouts: Rollouts, threads: usize, seed: u64) -> MonteCarlo {
+            MonteCarlo {
+                rollouts,
+                threads: threads.max(1),
+                rng: ChaCha8Rng::seed_from_u64(seed),
+            }
+        }
+
+        pub fn best_action(&mut self, game: &Game) -> Option<Action> {
+            let actions = game.get_valid_actions();
+            if actions.len() < 2 {
+                return actions.into_iter().next();
+            }
+
+            let seeds: Vec<u64> = (0..self.threads).map(|_| self.rng.gen()).collect();
+            let deadline = match self.rollouts {
+                Rollouts::Time(time) => Some(Instant::now() + time),
+                Rollouts::Playouts(_) => None,
+            };
+            let rounds = |worker: usize| match self.rollouts {
+                Rollouts::Playouts(n) => {
+                    let n = n as usize;
+                    n / self.threads + usize::from(worker < n % self.threads)
+                }
+                Rollouts::Time(_) => usize::MAX,
+            };
+
+            let results: Vec<Vec<(u64, u64)>> = thread::scope(|scope| {
+                let workers: Vec<_> = seeds
+                    .iter()
+                    .enumerate()
+                    .map(|(worker, seed)| {
+                        let actions = &actions;
+                        let rounds = rounds(worker);
+                        let seed = *seed;
+                        scope.spawn(move || rollouts(game, actions, rounds, deadline, seed))
+                    })
+                    .collect();
+                workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+            });
+
+            let mut best: Option<(Action, f64)> = None;
+            for (i, action) in actions.into_iter().enumerate() {
+                let (total, count) = results
+                    .iter()
+                    .fold((0, 0), |(total, count), worker| (total + worker[i].0, count + worker[i].1));
+                if count == 0 {
+                    continue;
+                }
+                let mean = total as f64 / count as f64;
+                match &best {
+                    Some((_, best_mean)) if mean <= *best_mean => {}
+                    _ => best = Some((action, mean)),
+                }
+            }
+            best.map(|(action, _)| action)
+        }
+    }
+
+    impl Player for MonteCarlo {
+        fn choose(&mut self, game: &Game) -> Action {
+            self.best_action(game)
+                .expect("choose is only called while a move is left")
+        }
+    }
+
+    // One worker's share: round-robin over the actions until `rounds` are done
+    // or the deadline passes, returning (total final score, playouts) per action.
+    fn rollouts(
+        game: &Game,
+        actions: &[Action],
+        rounds: usize,
+        deadline: Option<Instant>,
+        seed: u64,
+    ) -> Vec<(u64, u64)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut results = vec![(0, 0); actions.len()];
+        for round in 0..rounds {
+            if round > 0 && deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+                break;
+            }
+            for (action, result) in actions.iter().zip(results.iter_mut()) {
+                result.0 += playout(game, action, &mut rng) as u64;
+                result.1 += 1;
+            }
+        }
+        results
+    }
+
+    // Plays `action`, then random moves until the game ends. Spawns come from a
+    // fork so the real game's upcoming tiles are never peeked at.
+    fn playout(game: &Game, action: &Action, rng: &mut ChaCha8Rng) -> u32 {
+        let mut sim = game.fork(rng.gen());
+        sim.action(action);
+        while let Some(next) = sim.get_valid_actions().choose(rng).cloned() {
+            sim.action(&next);
+        }
+        sim.get_score()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn picks_a_valid_move() {
+            let mut game = Game::with_seed(3);
+            game.board = vec![
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 0],
+            ]
+            .into();
+            let mut player = MonteCarlo::with_seed(Rollouts::Playouts(4), 2, 1);
+            let action = player.best_action(&game).unwrap();
+            assert!(action == Action::MergeRight || action == Action::MergeDown);
+        }
+
+        #[test]
+        fn is_deterministic_with_a_seed() {
+            let game = Game::with_seed(11);
+            let mut a = MonteCarlo::with_seed(Rollouts::Playouts(8), 3, 7);
+            let mut b = MonteCarlo::with_seed(Rollouts::Playouts(8), 3, 7);
+            assert_eq!(a.best_action(&game), b.best_action(&game));
+
+            let mut lost = game.clone();
+            lost.board = vec![
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+                vec![2, 4, 2, 4],
+                vec![4, 2, 4, 2],
+            ]
+            .into();
+            assert!(a.best_action(&lost).is_none());
+        }
+    }
+}
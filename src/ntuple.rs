@@ -0,0 +1,305 @@
+pub mod ntuple {
+    //! An n-tuple network learned by temporal-difference updates on
+    //! afterstates (the board after a slide, before the spawn). A board's value
+    //! is the sum of weights looked up by the tiles under a few fixed groups of
+    //! cells, taken over all eight symmetries of the board, so what is learned
+    //! on one board carries over to every board sharing those patterns.
+    use std::fs::{self, File};
+    use std::io::{self, BufReader, BufWriter, Read, Write};
+    use std::path::{Path, PathBuf};
+    use std::time::Instant;
+
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::bitboard::bitboard::Bitboard;
+    use crate::game::oxydized2048::{Action, Game, GameConfig, GameStats};
+    use crate::player::player::Player;
+    use crate::termination::termination::{Budget, Limits};
+
+    /// Cells (`row * 4 + col`) of each tuple: the outer and inner lines, and
+    /// the corner, edge and centre squares. Their symmetries cover every row,
+    /// column and 2x2 square of the board.
+    pub const TUPLES: [[usize; 4]; 5] = [
+        [0, 1, 2, 3],
+        [4, 5, 6, 7],
+        [0, 1, 4, 5],
+        [1, 2, 5, 6],
+        [5, 6, 9, 10],
+    ];
+
+    /// Where the menu and command line keep the weights unless told otherwise.
+    pub const WEIGHTS_FILE: &str = "ntuple_weights.bin";
+
+    const MAGIC: &[u8; 4] = b"NTN1";
+
+    /// Features summed for one board, which `learn` spreads each update over.
+    const FEATURES: usize = TUPLES.len() * 8;
+
+    pub struct NTupleNetwork {
+        // One table per tuple, indexed by the four exponents packed as a u16
+        weights: Vec<Vec<f32>>,
+    }
+
+    impl NTupleNetwork {
+        pub fn new() -> NTupleNetwork {
+            NTupleNetwork {
+                weights: vec![vec![0.0; 1 << 16]; TUPLES.len()],
+            }
+        }
+
+        pub fn value(&self, board: Bitboard) -> f32 {
+            let mut value = 0.0;
+            for symmetry in board.symmetries() {
+                for (tuple, weights) in TUPLES.iter().zip(self.weights.iter()) {
+                    value += weights[index(symmetry, tuple)];
+                }
+            }
+            value
+        }
+
+        /// Moves the value of `afterstate` toward `target` by `alpha` of the error.
+        pub fn learn(&mut self, afterstate: Bitboard, target: f32, alpha: f32) {
+            let step = alpha * (target - self.value(afterstate)) / FEATURES as f32;
+            for symmetry in afterstate.symmetries() {
+                for (tuple, weights) in TUPLES.iter().zip(self.weights.iter_mut()) {
+                    weights[index(symmetry, tuple)] += step;
+                }
+            }
+        }
+
+        /// The move with the highest reward plus afterstate value, with that
+        /// afterstate and reward, or None when no move changes the board.
+        pub fn best_move(&self, board: Bitboard) -> Option<(Action, Bitboard, u32)> {
+            let mut best: Option<(Action, Bitboard, u32, f32)> = None;
+            for action in board.valid_actions() {
+                let (after, reward) = board.shift(&action);
+                let value = reward as f32 + self.value(after);
+                match &best {
+                    Some((_, _, _, best_value)) if value <= *best_value => {}
+                    _ => best = Some((action, after, reward, value)),
+                }
+            }
+            best.map(|(action, after, reward, _)| (action, after, reward))
+        }
+
+        /// Only 4x4 boards can be valued; on anything else this takes the first valid move.
+        pub fn best_action(&self, game: &Game) -> Option<Action> {
+            match game.board.bitboard() {
+                Some(board) => self.best_move(board).map(|(action, _, _)| action),
+                None => game.get_valid_actions().into_iter().next(),
+            }
+        }
+
+        /// Writes the tuples and then every weight, little-endian.
+        /// Writes next to `path` and renames over it once complete, so an
+        /// interrupted save keeps the previous file.
+        pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let path = path.as_ref();
+            let mut temporary = path.as_os_str().to_owned();
+            temporary.push(".tmp");
+            let temporary = PathBuf::from(temporary);
+
+            let written = self.write(&temporary);
+            if written.is_err() {
+                let _ = fs::remove_file(&temporary);
+                return written;
+            }
+            fs::rename(&temporary, path)
+        }
+
+        fn write(&self, path: &Path) -> io::Result<()> {
+            let mut file = BufWriter::new(File::create(path)?);
+            file.write_all(MAGIC)?;
+            file.write_all(&(TUPLES.len() as u32).to_le_bytes())?;
+            for tuple in TUPLES.iter() {
+                for cell in tuple {
+                    file.write_all(&[*cell as u8])?;
+                }
+            }
+            for weights in self.weights.iter() {
+                for weight in weights {
+                    file.write_all(&weight.to_le_bytes())?;
+                }
+            }
+            file.into_inner().map_err(|e| e.into_error())?.sync_all()
+        }
+
+        pub fn load<P: AsRef<Path>>(path: P) -> io::Result<NTupleNetwork> {
+            let mut file = BufReader::new(File::open(path)?);
+            let mut magic = [0; 4];
+            file.read_exact(&mut magic)?;
+            let mut count = [0; 4];
+            file.read_exact(&mut count)?;
+            let mut cells = vec![0; u32::from_le_bytes(count) as usize * 4];
+            file.read_exact(&mut cells)?;
+            let expected: Vec<u8> = TUPLES.iter().flatten().map(|cell| *cell as u8).collect();
+            if &magic != MAGIC || cells != expected {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a weight file for this network's tuples",
+                ));
+            }
+
+            let mut network = NTupleNetwork::new();
+            let mut bytes = [0; 4];
+            for weights in network.weights.iter_mut() {
+                for weight in weights.iter_mut() {
+                    file.read_exact(&mut bytes)?;
+                    *weight = f32::from_le_bytes(bytes);
+                }
+            }
+            Ok(network)
+        }
+    }
+
+    impl Player for NTupleNetwork {
+        fn choose(&mut self, game: &Game) -> Action {
+            (&*self).choose(game)
+        }
+    }
+
+    /// Lets threads share one network when playing.
+    impl Player for &NTupleNetwork {
+        fn choose(&mut self, game: &Game) -> Action {
+            self.best_action(game)
+                .expect("choose is only called while a move is left")
+        }
+    }
+
+    impl Default for NTupleNetwork {
+        fn default() -> NTupleNetwork {
+            NTupleNetwork::new()
+        }
+    }
+
+    fn index(board: Bitboard, tuple: &[usize; 4]) -> usize {
+        tuple
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, cell)| acc | (board.exponent(*cell) as usize) << (4 * i))
+    }
+
+    /// Plays one game greedily, learning from each move: the previous afterstate
+    /// is moved toward the next move's reward plus its afterstate's value, and a
+    /// final afterstate toward 0. Returns the finished game's stats.
+    pub fn train_episode(network: &mut NTupleNetwork, alpha: f32, config: &GameConfig, seed: u64) -> GameStats {
+        let mut game = Game::with_config_and_seed(config.clone(), seed);
+        game.set_history_depth(0);
+        let mut previous: Option<Bitboard> = None;
+        while let Some(board) = game.board.bitboard() {
+            match network.best_move(board) {
+                Some((action, after, reward)) => {
+                    if let Some(previous) = previous {
+                        let target = reward as f32 + network.value(after);
+                        network.learn(previous, target, alpha);
+                    }
+                    previous = Some(after);
+                    game.action(&action);
+                }
+                None => break,
+            }
+        }
+        if let Some(previous) = previous {
+            network.learn(previous, 0.0, alpha);
+        }
+        game.stats()
+    }
+
+    /// Trains until a game ends past `limits`, which must set at least one,
+    /// printing averages every `report` games. Games are dealt from `seed`
+    /// when there is one, so the same seed trains the same network.
+    pub fn train(
+        network: &mut NTupleNetwork,
+        alpha: f32,
+        limits: &Limits,
+        report: u32,
+        config: &GameConfig,
+        seed: Option<u64>,
+    ) {
+        assert!(
+            config.rows == 4 && config.cols == 4,
+            "the n-tuple network only plays 4x4 boards"
+        );
+        assert!(!limits.is_unlimited(), "n-tuple training needs a limit");
+        let start = Instant::now();
+        let mut dealer = match seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut budget = Budget::new(limits.clone());
+        let of = limits.episodes.map(|episodes| format!("/{}", episodes)).unwrap_or_default();
+        let mut total = 0;
+        let mut wins = 0;
+        let mut games = 0;
+        loop {
+            let stats = train_episode(network, alpha, config, dealer.gen());
+            let stop = budget.end_episode(stats.score);
+            total += stats.score as u64;
+            wins += stats.won as u32;
+            games += 1;
+            if games == report || stop {
+                let elapsed = start.elapsed().as_secs();
+                println!(
+                    "Episode: {}{} | Mean score: {:.0} | Win rate: {:.1}% | Elapsed: {}:{}",
+                    budget.episodes(),
+                    of,
+                    total as f64 / games as f64,
+                    100.0 * wins as f64 / games as f64,
+                    elapsed / 60,
+                    elapsed % 60
+                );
+                total = 0;
+                wins = 0;
+                games = 0;
+            }
+            if let Some(reason) = budget.stopped() {
+                println!("Stopped after {} training games: {}", budget.episodes(), reason);
+                return;
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::model::model::temp_path;
+
+        #[test]
+        fn symmetric_boards_share_a_value() {
+            let mut network = NTupleNetwork::new();
+            let board = Bitboard(0x0000_0000_0012_4321);
+            network.learn(board, 100.0, 0.5);
+            let value = network.value(board);
+            assert!(value > 0.0);
+            for symmetry in board.symmetries() {
+                assert!((network.value(symmetry) - value).abs() < 1e-3);
+            }
+        }
+
+        #[test]
+        fn saves_and_loads_weights() {
+            let mut network = NTupleNetwork::new();
+            network.learn(Bitboard(0x1111_0000_2345_0006), 42.0, 0.5);
+            let path = temp_path("ntuple.bin");
+            network.save(&path).unwrap();
+            let loaded = NTupleNetwork::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.weights, network.weights);
+            assert!(!temp_path("ntuple.bin.tmp").exists());
+        }
+
+        #[test]
+        fn training_plays_whole_games() {
+            let mut network = NTupleNetwork::new();
+            let stats = train_episode(&mut network, 0.1, &GameConfig::default(), 3);
+            assert!(stats.moves > 0);
+            assert!(network.weights.iter().flatten().any(|weight| *weight != 0.0));
+
+            // The same deal trains the same weights
+            let mut again = NTupleNetwork::new();
+            assert_eq!(train_episode(&mut again, 0.1, &GameConfig::default(), 3).score, stats.score);
+            assert_eq!(again.weights, network.weights);
+        }
+    }
+}
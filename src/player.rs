@@ -0,0 +1,269 @@
+pub mod player {
+    //! What every way of picking moves has in common, and the one loop that
+    //! plays a game out with any of them.
+    use rand::prelude::*;
+    use rand_chacha::ChaCha8Rng;
+    use serde::{Deserialize, Serialize};
+
+    use crate::game::oxydized2048::{Action, Game, GameConfig, GameState, GameStats};
+
+    pub trait Player {
+        /// Picks the next move. Only called while `game` has a valid move left;
+        /// picking an invalid one forfeits the game.
+        fn choose(&mut self, game: &Game) -> Action;
+
+        /// Takes a turn while `game` has a valid move left, which for most
+        /// players is just the move `choose` picks.
+        fn turn(&mut self, game: &Game) -> Turn {
+            Turn::Move(self.choose(game))
+        }
+
+        /// Takes a turn once `game` is over. Most players stop there, but a
+        /// person may take a move back.
+        fn game_over(&mut self, _game: &Game) -> Turn {
+            Turn::Stop
+        }
+    }
+
+    impl<P: Player + ?Sized> Player for Box<P> {
+        fn choose(&mut self, game: &Game) -> Action {
+            (**self).choose(game)
+        }
+
+        fn turn(&mut self, game: &Game) -> Turn {
+            (**self).turn(game)
+        }
+
+        fn game_over(&mut self, game: &Game) -> Turn {
+            (**self).game_over(game)
+        }
+    }
+
+    /// What a player does with a turn in `play_from`.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Turn {
+        Move(Action),
+        /// Takes back the last move played in `play_from`, if any.
+        Undo,
+        /// Plays the last move taken back again, if no move came since.
+        Redo,
+        /// Leaves the game where it stands.
+        Stop,
+    }
+
+    /// Picks uniformly among the valid moves, as a baseline to compare against.
+    pub struct Random {
+        rng: ChaCha8Rng,
+    }
+
+    impl Random {
+        pub fn with_seed(seed: u64) -> Random {
+            Random {
+                rng: ChaCha8Rng::seed_from_u64(seed),
+            }
+        }
+    }
+
+    impl Player for Random {
+        fn choose(&mut self, game: &Game) -> Action {
+            game.get_valid_actions()
+                .choose(&mut self.rng)
+                .cloned()
+                .expect("choose is only called while a move is left")
+        }
+    }
+
+    /// How much `play_game` prints while it runs.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Report {
+        Quiet,
+        /// One line with the final stats.
+        Result,
+        /// The final stats, plus the score and board every this many moves
+        /// and on reaching the win target.
+        Progress(u32),
+    }
+
+    /// A finished game: the config and seed it was dealt with and every move
+    /// played, which is enough to replay it exactly.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct GameRecord {
+        pub config: GameConfig,
+        pub stats: GameStats,
+        pub moves: Vec<Action>,
+    }
+
+    impl GameRecord {
+        /// Plays the recorded moves again from the recorded seed.
+        pub fn replay(&self) -> Game {
+            let mut game = Game::with_config_and_seed(self.config.clone(), self.stats.seed);
+            game.set_history_depth(0);
+            for action in self.moves.iter() {
+                game.action(action);
+            }
+            game
+        }
+    }
+
+    /// Plays a new game under `config` until it is over or `player` makes an
+    /// invalid move.
+    pub fn play_game<P: Player + ?Sized>(player: &mut P, config: &GameConfig, report: Report) -> GameRecord {
+        let mut game = Game::with_config(config.clone());
+        game.set_history_depth(0);
+        play_from(player, &mut game, report)
+    }
+
+    /// Like `play_game`, but continues `game` from wherever it stands until
+    /// the player stops or makes an invalid move, leaving it as it ended for
+    /// the caller to go on with. Only moves made here are recorded, and only
+    /// they can be taken back; the record leaves out any that were, so it
+    /// still replays to the final board.
+    pub fn play_from<P: Player + ?Sized>(player: &mut P, game: &mut Game, report: Report) -> GameRecord {
+        let mut moves = Vec::new();
+        // Moves taken back, the latest last, for redo
+        let mut undone = Vec::new();
+        let mut next_report = match report {
+            Report::Progress(every) => every.max(1),
+            _ => u32::MAX,
+        };
+        loop {
+            let turn = if game.is_gameover() {
+                player.game_over(game)
+            } else {
+                player.turn(game)
+            };
+            let action = match turn {
+                Turn::Move(action) => action,
+                Turn::Undo => {
+                    if !moves.is_empty() && game.undo() {
+                        undone.extend(moves.pop());
+                    }
+                    continue;
+                }
+                Turn::Redo => {
+                    if !undone.is_empty() && game.redo() {
+                        moves.extend(undone.pop());
+                    }
+                    continue;
+                }
+                Turn::Stop => break,
+            };
+            undone.clear();
+            match game.action(&action) {
+                GameState::InvalidMove => {
+                    if report != Report::Quiet {
+                        println!("Invalid move: {:?}", action);
+                    }
+                    break;
+                }
+                GameState::Won => {
+                    moves.push(action);
+                    if let Report::Progress(_) = report {
+                        println!("Reached {}!", game.config().win_target);
+                        game.display();
+                    }
+                }
+                GameState::Ok | GameState::Gameover => {
+                    moves.push(action);
+                    if let Report::Progress(every) = report {
+                        if moves.len() as u32 >= next_report {
+                            next_report += every.max(1);
+                            println!("Score: {}", game.get_score());
+                            game.display();
+                        }
+                    }
+                }
+            }
+        }
+
+        let stats = game.stats();
+        if report != Report::Quiet {
+            println!(
+                "Gameover (seed: {}) | Score: {} | Max tile: {} | Moves: {}",
+                stats.seed, stats.score, stats.max_tile, stats.moves
+            );
+        }
+        if let Report::Progress(_) = report {
+            game.display();
+        }
+        GameRecord {
+            config: game.config().clone(),
+            stats,
+            moves,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn records_a_game_that_replays_exactly() {
+            let mut player = Random::with_seed(9);
+            let record = play_game(&mut player, &GameConfig::default(), Report::Quiet);
+            assert_eq!(record.moves.len() as u32, record.stats.moves);
+
+            let replayed = record.replay();
+            assert!(replayed.is_gameover());
+            assert_eq!(replayed.stats(), record.stats);
+        }
+
+        #[test]
+        fn records_only_the_moves_left_standing() {
+            // Plays from a script, then stops
+            struct Scripted(Vec<Turn>);
+            impl Player for Scripted {
+                fn choose(&mut self, game: &Game) -> Action {
+                    game.get_valid_actions()[0].clone()
+                }
+
+                fn turn(&mut self, game: &Game) -> Turn {
+                    match self.0.pop() {
+                        Some(Turn::Move(_)) => Turn::Move(self.choose(game)),
+                        Some(turn) => turn,
+                        None => Turn::Stop,
+                    }
+                }
+            }
+
+            let mut game = Game::with_seed(12);
+            game.set_history_depth(10);
+            let script = [Turn::Undo, Turn::Move(Action::MergeLeft), Turn::Move(Action::MergeLeft)]
+                .into_iter()
+                .chain([Turn::Undo, Turn::Undo, Turn::Redo, Turn::Move(Action::MergeLeft), Turn::Redo])
+                .rev()
+                .collect();
+            let record = play_from(&mut Scripted(script), &mut game, Report::Quiet);
+
+            // The first undo has nothing of its own to take back, and the
+            // last redo comes after a new move
+            assert_eq!(record.moves.len(), 2);
+            assert_eq!(record.moves.len() as u32, record.stats.moves);
+            let replayed = record.replay();
+            assert_eq!(replayed.stats(), record.stats);
+            assert_eq!(replayed.board, game.board);
+            assert_eq!(game.stats(), record.stats);
+        }
+
+        #[test]
+        fn stops_on_an_invalid_move() {
+            struct AlwaysLeft;
+            impl Player for AlwaysLeft {
+                fn choose(&mut self, _: &Game) -> Action {
+                    Action::MergeLeft
+                }
+            }
+
+            let mut game = Game::with_seed(4);
+            game.board = vec![
+                vec![2, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+            ]
+            .into();
+            let record = play_from(&mut AlwaysLeft, &mut game, Report::Quiet);
+            assert!(record.moves.is_empty());
+        }
+    }
+}
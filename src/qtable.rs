@@ -0,0 +1,250 @@
+pub mod qtable {
+    //! A compact binary file for learned values, keyed by the board alone.
+    //!
+    //! The file is a header followed by one entry per board, read and written
+    //! one entry at a time so a table never has to be held twice in memory.
+    //! A board listed twice is merged when the table is loaded.
+    //!
+    //! - header: `R2QT`, a version byte, the metadata as a `u32` length and
+    //!   bincode bytes, then the board's rows and cols as one byte each
+    //! - entry: one tile exponent byte per cell (0 for empty), an action count
+    //!   byte, then per action its index byte and its value as an `f64`
+    //!
+    //! All numbers are little-endian and the entries simply run to the end of
+    //! the file. A file that breaks any of this reads as `InvalidData`.
+    use std::collections::HashMap;
+    use std::io::{self, Read, Write};
+
+    use crate::game::oxydized2048::Action;
+    use crate::model::model::Metadata;
+
+    pub type Board = Vec<Vec<u32>>;
+    /// A board and its learned values, as read back from a file.
+    pub type Entry = (Board, HashMap<Action, f64>);
+
+    const MAGIC: &[u8; 4] = b"R2QT";
+    const VERSION: u8 = 1;
+
+    const ACTIONS: [Action; 4] = [Action::MergeLeft, Action::MergeRight, Action::MergeUp, Action::MergeDown];
+
+    // Far more than any metadata takes, so a corrupt length can't ask for
+    // gigabytes before decoding fails
+    const MAX_METADATA: usize = 1 << 16;
+
+    fn invalid(message: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    fn action_index(action: &Action) -> u8 {
+        ACTIONS.iter().position(|a| a == action).unwrap() as u8
+    }
+
+    pub struct Writer<W: Write> {
+        inner: W,
+        rows: usize,
+        cols: usize,
+    }
+
+    impl<W: Write> Writer<W> {
+        /// Writes the header; every board written after must be `rows` x `cols`.
+        pub fn new(mut inner: W, metadata: &Metadata, rows: usize, cols: usize) -> io::Result<Writer<W>> {
+            if rows > u8::MAX as usize || cols > u8::MAX as usize {
+                return Err(invalid(format!("a {}x{} board is too large to store", rows, cols)));
+            }
+            let metadata = bincode::serialize(metadata).map_err(|e| invalid(e.to_string()))?;
+            inner.write_all(MAGIC)?;
+            inner.write_all(&[VERSION])?;
+            inner.write_all(&(metadata.len() as u32).to_le_bytes())?;
+            inner.write_all(&metadata)?;
+            inner.write_all(&[rows as u8, cols as u8])?;
+            Ok(Writer { inner, rows, cols })
+        }
+
+        /// Writes one board's values. Only the last value of an action listed
+        /// twice is kept.
+        pub fn write(&mut self, board: &Board, values: &[(Action, f64)]) -> io::Result<()> {
+            if board.len() != self.rows || board.iter().any(|row| row.len() != self.cols) {
+                return Err(invalid(format!("board is not {}x{}", self.rows, self.cols)));
+            }
+            let mut entry = Vec::with_capacity(self.rows * self.cols + 1 + values.len() * 9);
+            for tile in board.iter().flatten() {
+                match *tile {
+                    0 => entry.push(0),
+                    tile if tile.is_power_of_two() => entry.push(tile.trailing_zeros() as u8),
+                    tile => return Err(invalid(format!("{} is not a tile", tile))),
+                }
+            }
+            let count = entry.len();
+            entry.push(0);
+            // A fixed action order keeps the same table byte-for-byte the same file
+            for action in ACTIONS.iter() {
+                if let Some((_, value)) = values.iter().rev().find(|(a, _)| a == action) {
+                    entry[count] += 1;
+                    entry.push(action_index(action));
+                    entry.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            self.inner.write_all(&entry)
+        }
+
+        pub fn finish(mut self) -> io::Result<W> {
+            self.inner.flush()?;
+            Ok(self.inner)
+        }
+    }
+
+    /// Reads the header up front, then yields the entries one by one.
+    pub struct Reader<R: Read> {
+        inner: R,
+        metadata: Metadata,
+        rows: usize,
+        cols: usize,
+    }
+
+    impl<R: Read> Reader<R> {
+        pub fn new(mut inner: R) -> io::Result<Reader<R>> {
+            let mut magic = [0; 5];
+            inner.read_exact(&mut magic)?;
+            if &magic[..4] != MAGIC {
+                return Err(invalid("not a Q-table file".to_owned()));
+            }
+            if magic[4] != VERSION {
+                return Err(invalid(format!("Q-table version {} is not supported", magic[4])));
+            }
+            let mut length = [0; 4];
+            inner.read_exact(&mut length)?;
+            let length = u32::from_le_bytes(length) as usize;
+            if length > MAX_METADATA {
+                return Err(invalid(format!("{} bytes of metadata is more than a Q-table holds", length)));
+            }
+            let mut metadata = vec![0; length];
+            inner.read_exact(&mut metadata)?;
+            let metadata: Metadata = bincode::deserialize(&metadata).map_err(|e| invalid(e.to_string()))?;
+            let mut size = [0; 2];
+            inner.read_exact(&mut size)?;
+            let (rows, cols) = (size[0] as usize, size[1] as usize);
+            if rows == 0 || cols == 0 || (rows, cols) != (metadata.config.rows, metadata.config.cols) {
+                return Err(invalid(format!(
+                    "{}x{} boards don't match the {}x{} game the table was trained on",
+                    rows, cols, metadata.config.rows, metadata.config.cols
+                )));
+            }
+            Ok(Reader {
+                inner,
+                metadata,
+                rows,
+                cols,
+            })
+        }
+
+        pub fn metadata(&self) -> &Metadata {
+            &self.metadata
+        }
+
+        fn read_entry(&mut self) -> io::Result<Option<Entry>> {
+            let mut cells = vec![0; self.rows * self.cols + 1];
+            // The file may end cleanly between entries, but nowhere else
+            let mut read = 0;
+            while read < cells.len() {
+                match self.inner.read(&mut cells[read..]) {
+                    Ok(0) if read == 0 => return Ok(None),
+                    Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let (tiles, count) = cells.split_at(self.rows * self.cols);
+            // Exponents past 31 don't fit a u32 tile
+            if let Some(e) = tiles.iter().find(|e| **e >= 32) {
+                return Err(invalid(format!("2^{} is not a tile", e)));
+            }
+            if count[0] as usize > ACTIONS.len() {
+                return Err(invalid(format!("{} actions for one board", count[0])));
+            }
+            let board = tiles
+                .chunks(self.cols)
+                .map(|row| row.iter().map(|e| if *e == 0 { 0 } else { 1 << e }).collect())
+                .collect();
+            let mut values = HashMap::new();
+            for _ in 0..count[0] {
+                let mut value = [0; 9];
+                self.inner.read_exact(&mut value)?;
+                let action = ACTIONS
+                    .get(value[0] as usize)
+                    .ok_or_else(|| invalid(format!("{} is not an action", value[0])))?;
+                values.insert(action.clone(), f64::from_le_bytes(value[1..].try_into().unwrap()));
+            }
+            Ok(Some((board, values)))
+        }
+    }
+
+    impl<R: Read> Iterator for Reader<R> {
+        type Item = io::Result<Entry>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            self.read_entry().transpose()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::game::oxydized2048::GameConfig;
+        use crate::model::model::Hyperparameters;
+
+        #[test]
+        fn writes_and_streams_back_entries() {
+            let metadata = Metadata::new(Hyperparameters::default(), GameConfig::with_size(3, 2));
+            let boards: Vec<Board> = vec![
+                vec![vec![0, 2], vec![4, 0], vec![0, 2048]],
+                vec![vec![0, 0], vec![0, 0], vec![0, 65536]],
+            ];
+            let values = [(Action::MergeDown, 0.75), (Action::MergeLeft, -3.5)];
+
+            let mut writer = Writer::new(Vec::new(), &metadata, 3, 2).unwrap();
+            for board in boards.iter() {
+                writer.write(board, &values).unwrap();
+            }
+            assert!(writer.write(&vec![vec![0; 4]; 4], &values).is_err());
+            let bytes = writer.finish().unwrap();
+
+            let reader = Reader::new(bytes.as_slice()).unwrap();
+            assert_eq!(reader.metadata(), &metadata);
+            let entries: Vec<_> = reader.collect::<io::Result<_>>().unwrap();
+            assert_eq!(entries.len(), 2);
+            for ((board, read), expected) in entries.iter().zip(boards.iter()) {
+                assert_eq!(board, expected);
+                assert_eq!(read, &values.iter().cloned().collect());
+            }
+
+            let truncated = Reader::new(&bytes[..bytes.len() - 3]).unwrap();
+            assert!(truncated.collect::<io::Result<Vec<_>>>().is_err());
+        }
+
+        #[test]
+        fn rejects_corrupt_files() {
+            let metadata = Metadata::new(Hyperparameters::default(), GameConfig::default());
+            let mut writer = Writer::new(Vec::new(), &metadata, 4, 4).unwrap();
+            writer.write(&vec![vec![2, 0, 0, 0]; 4], &[(Action::MergeUp, 1.0)]).unwrap();
+            let bytes = writer.finish().unwrap();
+            let size = 9 + u32::from_le_bytes(bytes[5..9].try_into().unwrap()) as usize;
+            let entry = size + 2;
+
+            let corrupt = |at: usize, byte: u8| {
+                let mut bytes = bytes.clone();
+                bytes[at] = byte;
+                match Reader::new(bytes.as_slice()) {
+                    Ok(reader) => reader.collect::<io::Result<Vec<_>>>().map(|_| ()),
+                    Err(e) => Err(e),
+                }
+            };
+            assert!(corrupt(entry, 1).is_ok());
+            for (at, byte) in [(8, 0xff), (size, 0), (size + 1, 5), (entry, 32), (entry + 16, 5)] {
+                let read = corrupt(at, byte);
+                assert_eq!(read.unwrap_err().kind(), io::ErrorKind::InvalidData, "byte {} set to {}", at, byte);
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+pub mod termination {
+    //! When training stops, judged as each training game ends: after a number
+    //! of games, after a stretch of wall time, once the mean score reaches a
+    //! target, or once it stops improving. Every training mode reports its
+    //! finished games to a `Budget`, so the same limits work for all of them.
+    use std::collections::VecDeque;
+    use std::fmt;
+    use std::time::{Duration, Instant};
+
+    /// Limits on training, any of which stops it. None set means no limit.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct Limits {
+        pub episodes: Option<u64>,
+        pub time: Option<Duration>,
+        /// Mean score over the last `window` games.
+        pub target_score: Option<f64>,
+        /// Games the mean score over the last `window` games may go without
+        /// a new best.
+        pub patience: Option<u64>,
+        pub window: usize,
+    }
+
+    impl Limits {
+        pub fn episodes(episodes: u64) -> Limits {
+            Limits {
+                episodes: Some(episodes),
+                ..Limits::default()
+            }
+        }
+
+        pub fn is_unlimited(&self) -> bool {
+            self.episodes.is_none() && self.time.is_none() && self.target_score.is_none() && self.patience.is_none()
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Reason {
+        Episodes,
+        Time,
+        TargetScore,
+        Plateau,
+    }
+
+    impl fmt::Display for Reason {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Reason::Episodes => write!(f, "played every training game"),
+                Reason::Time => write!(f, "out of time"),
+                Reason::TargetScore => write!(f, "reached the target score"),
+                Reason::Plateau => write!(f, "the mean score stopped improving"),
+            }
+        }
+    }
+
+    /// Counts finished games against `Limits`, from when it was made.
+    #[derive(Clone, Debug)]
+    pub struct Budget {
+        limits: Limits,
+        start: Instant,
+        episodes: u64,
+        // Scores of the last `window` games and their sum
+        recent: VecDeque<u32>,
+        sum: u64,
+        best: f64,
+        since_best: u64,
+        stopped: Option<Reason>,
+    }
+
+    impl Budget {
+        pub fn new(limits: Limits) -> Budget {
+            Budget {
+                limits: Limits {
+                    window: limits.window.max(1),
+                    ..limits
+                },
+                start: Instant::now(),
+                episodes: 0,
+                recent: VecDeque::new(),
+                sum: 0,
+                best: f64::NEG_INFINITY,
+                since_best: 0,
+                stopped: None,
+            }
+        }
+
+        pub fn limits(&self) -> &Limits {
+            &self.limits
+        }
+
+        pub fn episodes(&self) -> u64 {
+            self.episodes
+        }
+
+        /// Why training should stop, once a finished game has used up the budget.
+        pub fn stopped(&self) -> Option<Reason> {
+            self.stopped
+        }
+
+        /// Counts a finished game that scored `score`. Returns whether
+        /// training should stop; once it should, it stays that way.
+        pub fn end_episode(&mut self, score: u32) -> bool {
+            self.episodes += 1;
+            if self.recent.len() == self.limits.window {
+                self.sum -= self.recent.pop_front().unwrap() as u64;
+            }
+            self.recent.push_back(score);
+            self.sum += score as u64;
+
+            // Only a full window has a mean worth judging
+            let mean = if self.recent.len() == self.limits.window {
+                Some(self.sum as f64 / self.limits.window as f64)
+            } else {
+                None
+            };
+            if let Some(mean) = mean {
+                if mean > self.best {
+                    self.best = mean;
+                    self.since_best = 0;
+                } else {
+                    self.since_best += 1;
+                }
+            }
+
+            if self.stopped.is_none() {
+                self.stopped = if self.limits.episodes.is_some_and(|episodes| self.episodes >= episodes) {
+                    Some(Reason::Episodes)
+                } else if self.limits.time.is_some_and(|time| self.start.elapsed() >= time) {
+                    Some(Reason::Time)
+                } else if mean.zip(self.limits.target_score).is_some_and(|(mean, target)| mean >= target) {
+                    Some(Reason::TargetScore)
+                } else if self.limits.patience.is_some_and(|patience| self.since_best >= patience) {
+                    Some(Reason::Plateau)
+                } else {
+                    None
+                };
+            }
+            self.stopped.is_some()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn run(limits: Limits, scores: &[u32]) -> (u64, Option<Reason>) {
+            let mut budget = Budget::new(limits);
+            for score in scores {
+                if budget.end_episode(*score) {
+                    break;
+                }
+            }
+            (budget.episodes(), budget.stopped())
+        }
+
+        #[test]
+        fn stops_at_the_first_limit_reached() {
+            let scores = [100, 200, 300, 400, 400, 400, 400, 400];
+            assert_eq!(run(Limits::default(), &scores), (8, None));
+            assert_eq!(run(Limits::episodes(3), &scores), (3, Some(Reason::Episodes)));
+            let time = Limits {
+                time: Some(Duration::ZERO),
+                ..Limits::default()
+            };
+            assert_eq!(run(time, &scores), (1, Some(Reason::Time)));
+
+            // Means of two games: 150, 250, 350, 400, 400, ...
+            let target = Limits {
+                target_score: Some(350.0),
+                window: 2,
+                ..Limits::default()
+            };
+            assert_eq!(run(target, &scores), (4, Some(Reason::TargetScore)));
+            let plateau = Limits {
+                patience: Some(3),
+                window: 2,
+                ..Limits::default()
+            };
+            assert_eq!(run(plateau.clone(), &scores), (8, Some(Reason::Plateau)));
+            let patient = Limits {
+                patience: Some(4),
+                ..plateau
+            };
+            assert_eq!(run(patient, &scores), (8, None));
+        }
+    }
+}
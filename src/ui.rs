@@ -0,0 +1,338 @@
+pub mod ui {
+    use std::io;
+
+    use crossterm::cursor::Show;
+    use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+    use crossterm::execute;
+    use crossterm::terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+    };
+    use tui::backend::{Backend, CrosstermBackend};
+    use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
+    use tui::style::{Color, Modifier, Style};
+    use tui::text::{Span, Spans};
+    use tui::widgets::{Block, Borders, Clear, Paragraph};
+    use tui::{Frame, Terminal};
+
+    use crate::game::oxydized2048::{Action, Game, GameConfig};
+    use crate::player::player::{play_from, Player, Report, Turn};
+
+    const UNDO_DEPTH: usize = 100;
+
+    const TILE_WIDTH: u16 = 8;
+    const TILE_HEIGHT: u16 = 3;
+
+    const DARK_TEXT: Color = Color::Rgb(119, 110, 101);
+    const LIGHT_TEXT: Color = Color::Rgb(249, 246, 242);
+
+    // What the board shows on top of it.
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Popup {
+        GameOver,
+        Won,
+    }
+
+    // The person at the keyboard, shown the game full-screen. Restarting or
+    // quitting stops the game; `play` tells them apart by `restart`.
+    struct Keyboard<B: Backend> {
+        terminal: Terminal<B>,
+        best: u32,
+        // Set once the player picks keep going after a win, until it is undone
+        kept_going: bool,
+        restart: bool,
+        // Stops the game, to be handed back by `play`
+        error: Option<io::Error>,
+    }
+
+    impl<B: Backend> Keyboard<B> {
+        fn new(terminal: Terminal<B>) -> Keyboard<B> {
+            Keyboard {
+                terminal,
+                best: 0,
+                kept_going: false,
+                restart: false,
+                error: None,
+            }
+        }
+
+        // Shows `game` and waits for a key press, or None on a terminal error.
+        fn read_key(&mut self, game: &Game, popup: Option<Popup>) -> Option<KeyEvent> {
+            self.best = self.best.max(game.get_score());
+            let best = self.best;
+            loop {
+                if let Err(error) = self.terminal.draw(|f| draw(f, game, best, popup)) {
+                    self.error = Some(error);
+                    return None;
+                }
+                match event::read() {
+                    Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => return Some(key),
+                    Ok(_) => {}
+                    Err(error) => {
+                        self.error = Some(error);
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    impl<B: Backend> Player for Keyboard<B> {
+        /// Waits for a key that moves the board.
+        fn choose(&mut self, game: &Game) -> Action {
+            let valid = game.get_valid_actions();
+            loop {
+                let action = self.read_key(game, None).and_then(|key| key_to_action(key.code));
+                match action {
+                    Some(action) if valid.contains(&action) => return action,
+                    // Nothing left to read; any move will do
+                    _ if self.error.is_some() => return valid[0].clone(),
+                    _ => {}
+                }
+            }
+        }
+
+        fn turn(&mut self, game: &Game) -> Turn {
+            if !game.has_won() {
+                self.kept_going = false;
+            }
+            loop {
+                let won = game.has_won() && !self.kept_going;
+                let Some(key) = self.read_key(game, won.then_some(Popup::Won)) else {
+                    return Turn::Stop;
+                };
+                match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Turn::Stop,
+                    KeyCode::Char('q') | KeyCode::Esc => return Turn::Stop,
+                    KeyCode::Char('r') => {
+                        self.restart = true;
+                        return Turn::Stop;
+                    }
+                    KeyCode::Char('c') | KeyCode::Enter if won => self.kept_going = true,
+                    KeyCode::Char('u') if !won => return Turn::Undo,
+                    KeyCode::Char('y') if !won => return Turn::Redo,
+                    code if !won => {
+                        // A move that changes nothing is no turn at all
+                        match key_to_action(code) {
+                            Some(action) if game.get_valid_actions().contains(&action) => return Turn::Move(action),
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        fn game_over(&mut self, game: &Game) -> Turn {
+            loop {
+                let Some(key) = self.read_key(game, Some(Popup::GameOver)) else {
+                    return Turn::Stop;
+                };
+                match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Turn::Stop,
+                    KeyCode::Char('q') | KeyCode::Esc => return Turn::Stop,
+                    KeyCode::Char('r') => {
+                        self.restart = true;
+                        return Turn::Stop;
+                    }
+                    KeyCode::Char('u') => return Turn::Undo,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Runs the game full-screen until the player quits. A `seed` deals the
+    /// same game, and the same games after each restart, every time.
+    pub fn play(config: GameConfig, seed: Option<u64>) -> io::Result<()> {
+        let _screen = FullScreen::enter()?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        terminal.hide_cursor()?;
+
+        let mut keyboard = Keyboard::new(terminal);
+        let game = match seed {
+            Some(seed) => Game::with_config_and_seed(config, seed),
+            None => Game::with_config(config),
+        };
+        run(&mut keyboard, game)
+    }
+
+    // Raw mode on the alternate screen, left again when dropped, so the
+    // terminal is given back however `play` ends, panics included.
+    struct FullScreen;
+
+    impl FullScreen {
+        fn enter() -> io::Result<FullScreen> {
+            enable_raw_mode()?;
+            let screen = FullScreen;
+            execute!(io::stdout(), EnterAlternateScreen)?;
+            Ok(screen)
+        }
+    }
+
+    impl Drop for FullScreen {
+        fn drop(&mut self) {
+            // Each step is worth trying even if another fails
+            let _ = disable_raw_mode();
+            let _ = execute!(io::stdout(), LeaveAlternateScreen, Show);
+        }
+    }
+
+    fn run<B: Backend>(keyboard: &mut Keyboard<B>, mut game: Game) -> io::Result<()> {
+        game.set_history_depth(UNDO_DEPTH);
+        loop {
+            keyboard.restart = false;
+            play_from(keyboard, &mut game, Report::Quiet);
+            if let Some(error) = keyboard.error.take() {
+                return Err(error);
+            }
+            if !keyboard.restart {
+                return Ok(());
+            }
+            game.reset();
+        }
+    }
+
+    fn key_to_action(code: KeyCode) -> Option<Action> {
+        match code {
+            KeyCode::Up | KeyCode::Char('w') => Some(Action::MergeUp),
+            KeyCode::Left | KeyCode::Char('a') => Some(Action::MergeLeft),
+            KeyCode::Down | KeyCode::Char('s') => Some(Action::MergeDown),
+            KeyCode::Right | KeyCode::Char('d') => Some(Action::MergeRight),
+            _ => None,
+        }
+    }
+
+    fn draw<B: Backend>(f: &mut Frame<B>, game: &Game, best: u32, popup: Option<Popup>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(0),
+                Constraint::Length(1),
+            ])
+            .split(f.size());
+
+        draw_header(f, game, best, chunks[0]);
+        let board = draw_board(f, game, chunks[1]);
+
+        let help = Paragraph::new("arrows/wasd: move   u: undo   y: redo   r: restart   q: quit")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        f.render_widget(help, chunks[2]);
+
+        if popup == Some(Popup::GameOver) {
+            let stats = game.stats();
+            let summary = format!(
+                "Score: {}  Max: {}  Moves: {}",
+                stats.score, stats.max_tile, stats.moves
+            );
+            draw_popup(f, "Game over!", &summary, "u: undo   r: restart   q: quit", board);
+        } else if popup == Some(Popup::Won) {
+            let title = format!("You made {}!", game.config().win_target);
+            let score = format!("Score: {}", game.get_score());
+            draw_popup(f, &title, &score, "c: keep going   r: new game", board);
+        }
+    }
+
+    fn draw_header<B: Backend>(f: &mut Frame<B>, game: &Game, best: u32, area: Rect) {
+        let label = Style::default().fg(Color::Gray);
+        let value = Style::default().add_modifier(Modifier::BOLD);
+        let header = Paragraph::new(Spans::from(vec![
+            Span::styled("Score: ", label),
+            Span::styled(game.get_score().to_string(), value),
+            Span::raw("    "),
+            Span::styled("Best: ", label),
+            Span::styled(best.to_string(), value),
+            Span::raw("    "),
+            Span::styled("Seed: ", label),
+            Span::styled(game.seed().to_string(), label),
+        ]))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" 2048 "));
+        f.render_widget(header, area);
+    }
+
+    fn draw_board<B: Backend>(f: &mut Frame<B>, game: &Game, area: Rect) -> Rect {
+        let rows = game.rows() as u16;
+        let cols = game.cols() as u16;
+        let board = centered(cols * TILE_WIDTH + 2, rows * TILE_HEIGHT + 2, area);
+
+        let block = Block::default().borders(Borders::ALL);
+        let inner = block.inner(board);
+        f.render_widget(block, board);
+
+        for (i, row) in game.board.to_rows().iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let tile = Rect {
+                    x: inner.x + j as u16 * TILE_WIDTH,
+                    y: inner.y + i as u16 * TILE_HEIGHT,
+                    width: TILE_WIDTH,
+                    height: TILE_HEIGHT,
+                }
+                .intersection(inner);
+                if tile.area() == 0 {
+                    continue;
+                }
+
+                let text = if *value == 0 {
+                    String::new()
+                } else {
+                    format!("\n{}", value)
+                };
+                let paragraph = Paragraph::new(text)
+                    .style(tile_style(*value))
+                    .alignment(Alignment::Center);
+                f.render_widget(paragraph, tile);
+            }
+        }
+        board
+    }
+
+    fn draw_popup<B: Backend>(f: &mut Frame<B>, title: &str, body: &str, keys: &str, board: Rect) {
+        let area = centered(42, 6, board);
+        let text = vec![
+            Spans::from(Span::styled(
+                title,
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(body),
+            Spans::from(""),
+            Spans::from(keys),
+        ];
+        let popup = Paragraph::new(text)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
+    fn tile_style(value: u32) -> Style {
+        let (bg, fg) = match value {
+            0 => (Color::Rgb(205, 193, 180), DARK_TEXT),
+            2 => (Color::Rgb(238, 228, 218), DARK_TEXT),
+            4 => (Color::Rgb(237, 224, 200), DARK_TEXT),
+            8 => (Color::Rgb(242, 177, 121), LIGHT_TEXT),
+            16 => (Color::Rgb(245, 149, 99), LIGHT_TEXT),
+            32 => (Color::Rgb(246, 124, 95), LIGHT_TEXT),
+            64 => (Color::Rgb(246, 94, 59), LIGHT_TEXT),
+            128 => (Color::Rgb(237, 207, 114), LIGHT_TEXT),
+            256 => (Color::Rgb(237, 204, 97), LIGHT_TEXT),
+            512 => (Color::Rgb(237, 200, 80), LIGHT_TEXT),
+            1024 => (Color::Rgb(237, 197, 63), LIGHT_TEXT),
+            2048 => (Color::Rgb(237, 194, 46), LIGHT_TEXT),
+            _ => (Color::Rgb(60, 58, 50), LIGHT_TEXT),
+        };
+        Style::default().bg(bg).fg(fg).add_modifier(Modifier::BOLD)
+    }
+
+    fn centered(width: u16, height: u16, area: Rect) -> Rect {
+        let width = width.min(area.width);
+        let height = height.min(area.height);
+        Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        }
+    }
+}